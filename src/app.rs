@@ -1,25 +1,73 @@
 // The application egui front end
 
 use core::f32;
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::Display;
+use std::net::{SocketAddrV4, SocketAddrV6};
 use std::path::PathBuf;
+use std::str::FromStr;
 
 use crate::comms::{Command, Event, MessageDisplay, ProgressList};
+use crate::transport::{
+    AddrInfoOptions, BUNDLE_EXTENSION, ConnectionDiag, Contacts, DryRun, FetchOptions, GcOptions,
+    GcReport, ImportChoice, Inbox, IncomingOffer, Limits, NearbyPeer, NetOptions, Offer, PathKind,
+    Preview, RelayModeOption, ReplicaState, SeedStats, SendOptions, ShareVersion, TICKET_EXTENSION,
+    TagEntry, TicketFile, TreeItem, VerifyReport, is_ticket, lenient_relay, ticket_node,
+};
 use crate::worker::{Worker, WorkerHandle};
 use anyhow::Result;
 use directories::{BaseDirs, UserDirs};
 use eframe::NativeOptions;
-use eframe::egui::{self, FontId, RichText, Visuals};
+use eframe::egui::{self, Color32, ColorImage, FontId, TextureHandle, TextureOptions, Visuals};
 use egui::Ui;
-use rfd;
+use humansize::{DECIMAL, format_size};
+use iroh::NodeId;
+use iroh_blobs::Hash;
 use serde_derive::{Deserialize, Serialize};
+use tracing::{error, info, warn};
 
 // Application saved config
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     dark_mode: bool,
     download_path: PathBuf,
     store_path: PathBuf,
+    // Default import mode for sending
+    import_choice: ImportChoice,
+    // Files under this size (MB) are copied in auto mode
+    copy_threshold_mb: u64,
+    // Send file modes, times and empty folders
+    preserve_metadata: bool,
+    // What goes into a ticket
+    ticket_type: AddrInfoOptions,
+    // Store limits , zero is off
+    store_max_gb: u64,
+    incoming_expiry_days: u64,
+    gc_interval_hours: u64,
+    // Keep the store in memory , nothing is left after exit
+    ephemeral: bool,
+    // Relay servers , default , disabled or a url
+    #[serde(deserialize_with = "lenient_relay")]
+    relay: RelayModeOption,
+    // Local addresses , port 0 is random
+    bind_v4: SocketAddrV4,
+    bind_v6: SocketAddrV6,
+    // Node ids that replicas go to and come from
+    replica_peers: Vec<String>,
+    // Push every new share to the peers
+    replicate_shares: bool,
+    // Find nodes on the LAN over mdns
+    local_discovery: bool,
+    // Announced to nearby devices
+    device_name: String,
+    // Shared rate limits in kB/s , zero is off
+    upload_limit_kb: u64,
+    download_limit_kb: u64,
+    // Take collections pushed by other nodes
+    inbox: bool,
+    // Decline pushes from nodes not in the contacts
+    inbox_contacts_only: bool,
 }
 
 impl Default for Config {
@@ -40,10 +88,130 @@ impl Default for Config {
             dark_mode: true,
             download_path,
             store_path,
+            import_choice: ImportChoice::Auto,
+            copy_threshold_mb: 100,
+            preserve_metadata: true,
+            ticket_type: AddrInfoOptions::RelayAndAddresses,
+            store_max_gb: 0,
+            incoming_expiry_days: 0,
+            gc_interval_hours: 24,
+            ephemeral: false,
+            relay: RelayModeOption::Default,
+            bind_v4: NetOptions::default().bind_v4,
+            bind_v6: NetOptions::default().bind_v6,
+            replica_peers: Vec::new(),
+            replicate_shares: false,
+            local_discovery: false,
+            device_name: host_name(),
+            upload_limit_kb: 0,
+            download_limit_kb: 0,
+            inbox: false,
+            inbox_contacts_only: true,
         }
     }
 }
 
+impl Config {
+    // Store limits for the worker
+    fn gc_options(&self) -> GcOptions {
+        GcOptions {
+            max_store_size: self.store_max_gb * 1000 * 1000 * 1000,
+            incoming_expiry_days: self.incoming_expiry_days,
+            interval_hours: self.gc_interval_hours,
+        }
+    }
+
+    fn net_options(&self) -> NetOptions {
+        NetOptions {
+            relay: self.relay.clone(),
+            bind_v4: self.bind_v4,
+            bind_v6: self.bind_v6,
+            local_discovery: self.local_discovery,
+            device_name: self.device_name.clone(),
+        }
+    }
+
+    // The shared limits go straight to the worker
+    fn apply_limits(&self, limits: &Limits) {
+        limits.upload.set(self.upload_limit_kb * 1000);
+        limits.download.set(self.download_limit_kb * 1000);
+    }
+
+    // The senders the inbox takes offers from
+    fn apply_senders(&self, inbox: &Inbox, contacts: &Contacts) {
+        let known = contacts.iter().map(|contact| contact.node_id).collect();
+        inbox.set_senders(self.inbox_contacts_only, known);
+    }
+
+    // Replication peers for the worker , bad ids are skipped
+    fn replica_settings(&self) -> Command {
+        let peers = self
+            .replica_peers
+            .iter()
+            .filter_map(|peer| match NodeId::from_str(peer) {
+                Ok(node_id) => Some(node_id),
+                Err(err) => {
+                    warn!("bad replication peer {peer} {err}");
+                    None
+                }
+            })
+            .collect();
+        Command::ReplicaSettings((peers, self.replicate_shares))
+    }
+}
+
+// Rate of one job in kB/s , true when it was changed
+fn limit_value(ui: &mut Ui, label: &str, value: &mut u64) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let changed = ui
+            .add(egui::DragValue::new(value).speed(10).suffix(" kB/s"))
+            .changed();
+        if *value == 0 {
+            ui.small("off");
+        }
+        changed
+    })
+    .inner
+}
+
+// The saved config , the defaults if it can not be read
+fn load_config() -> Config {
+    confy::load("sendme-egui", None).unwrap_or_else(|err| {
+        warn!("failed to load config , using the defaults {err}");
+        Config::default()
+    })
+}
+
+// The saved contacts , none if they can not be read
+fn load_contacts() -> Contacts {
+    Contacts::load(&contacts_path()).unwrap_or_else(|err| {
+        warn!("failed to read contacts {err}");
+        Contacts::default()
+    })
+}
+
+// The address book sits next to the store in the data dir
+fn contacts_path() -> PathBuf {
+    match BaseDirs::new() {
+        Some(base_dirs) => base_dirs
+            .data_dir()
+            .join("sendme-egui")
+            .join("contacts.json"),
+        None => PathBuf::from("contacts.json"),
+    }
+}
+
+// Default device name , what the system calls itself
+fn host_name() -> String {
+    ["HOSTNAME", "COMPUTERNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .unwrap_or_default()
+}
+
 // Message list max
 const MESSAGE_MAX: usize = 50;
 
@@ -63,6 +231,9 @@ enum AppMode {
     FetchProgess,
     Finished,
     Config,
+    Store,
+    Seed,
+    Contacts,
 }
 
 impl Display for AppMode {
@@ -75,22 +246,100 @@ impl Display for AppMode {
             AppMode::FetchProgess => "Fetch Running...",
             AppMode::Finished => "Finished",
             AppMode::Config => "Config",
+            AppMode::Store => "Store",
+            AppMode::Seed => "Seeding",
+            AppMode::Contacts => "Contacts",
         };
         write!(f, "{}", val)
     }
 }
 
+// Things to do from the store browser
+enum StoreAction {
+    Inspect(Hash),
+    Export(Hash),
+    Bundle(TagEntry),
+    Share(TagEntry),
+    Pin(TagEntry),
+    Seed(TagEntry),
+    Replicate(TagEntry),
+    Push((TagEntry, NodeId)),
+    Edit(TagEntry),
+    Delete(String),
+}
+
+// Name and note of a tag being edited in the store browser
+struct TagEdit {
+    tag: String,
+    direction: Option<&'static str>,
+    label: String,
+    note: String,
+    // What was there , only changes are sent
+    old_label: String,
+    old_note: String,
+}
+
 // Internal state for the application
 struct AppState {
-    picked_path: Option<PathBuf>,
+    // Paths to send , also the drop staging area
+    picked_paths: Vec<PathBuf>,
     worker: WorkerHandle,
     mode: AppMode,
     receiver_ticket: String,
     send_ticket: Option<String>,
+    show_qr: bool,
+    qr_texture: Option<(String, TextureHandle)>,
+    ticket_note: Option<String>,
+    fetch_options: FetchOptions,
+    preview: Option<Preview>,
+    dry_run: Option<DryRun>,
+    share_name: Option<String>,
+    shares: Vec<TagEntry>,
+    // Store browser
+    store_tags: Vec<TagEntry>,
+    tree: Option<(Hash, Vec<TreeItem>)>,
+    delete_tag: Option<String>,
+    tag_search: String,
+    edit_tag: Option<TagEdit>,
+    gc_report: Option<GcReport>,
+    // Why the worker stopped
+    fatal: Option<String>,
+    verify: Option<VerifyReport>,
+    drop_blob: Option<Hash>,
+    // Seeding dashboard
+    seeds: Vec<SeedStats>,
+    // Replication , the id of this node and the pushes to the peers
+    node_id: Option<NodeId>,
+    peer_input: String,
+    // Network settings as typed in the config
+    relay_input: String,
+    bind_v4_input: String,
+    bind_v6_input: String,
+    replicas: BTreeMap<(String, NodeId), ReplicaState>,
+    // Named nodes found on the local network
+    nearby: Vec<NearbyPeer>,
+    // Pushes waiting for accept or decline
+    offers: Vec<IncomingOffer>,
+    // Paths of the send and fetch connections
+    connections: Vec<ConnectionDiag>,
+    // Address book , kept in the data dir
+    contacts: Contacts,
+    contact_name_input: String,
+    contact_id_input: String,
+    // Limits of the next or running share and fetch in kB/s
+    share_limit_kb: u64,
+    fetch_limit_kb: u64,
+    remove_old_store: bool,
+    versions: Vec<ShareVersion>,
+    watch: bool,
     progress: ProgressList,
     messages: Vec<MessageDisplay>,
     config: Config,
+    // Running on the in-memory store
+    ephemeral: bool,
     elapsed: Option<u64>,
+    import_choice: ImportChoice,
+    ticket_type: AddrInfoOptions,
 }
 
 // Make the egui impl for display
@@ -107,6 +356,16 @@ impl eframe::App for App {
             let ctx = ctx.clone();
             let callback = Box::new(move || ctx.request_repaint());
             self.state.cmd(Command::Setup { callback });
+            self.state.cmd(Command::ListShares);
+            self.state
+                .cmd(Command::GcSettings(self.state.config.gc_options()));
+            self.state
+                .cmd(Command::NetSettings(self.state.config.net_options()));
+            self.state.cmd(self.state.config.replica_settings());
+            self.state
+                .cmd(Command::InboxSettings(self.state.config.inbox));
+            self.state.apply_senders();
+            self.state.config.apply_limits(&self.state.worker.limits);
         }
         self.state.update(ctx);
     }
@@ -115,22 +374,64 @@ impl eframe::App for App {
 // The application runner start,draw, etc...
 // Spawns the worker as a subthread
 impl App {
-    pub fn run(options: NativeOptions) -> Result<(), eframe::Error> {
+    pub fn run(options: NativeOptions, ephemeral: bool) -> Result<(), eframe::Error> {
         // Load the config
-        let config: Config = confy::load("sendme-egui", None).unwrap_or_default();
+        let config = load_config();
+        let ephemeral = ephemeral || config.ephemeral;
 
         // Start up the worker , separate thread , async runner
-        let handle = Worker::spawn(config.store_path.clone());
+        let handle = Worker::spawn(config.store_path.clone(), ephemeral);
 
         let state = AppState {
-            picked_path: None,
+            picked_paths: Vec::new(),
             worker: handle,
             mode: AppMode::Init,
             receiver_ticket: String::new(),
             send_ticket: None,
+            show_qr: false,
+            qr_texture: None,
+            ticket_note: None,
+            fetch_options: FetchOptions {
+                ephemeral,
+                ..FetchOptions::default()
+            },
+            preview: None,
+            dry_run: None,
+            share_name: None,
+            shares: Vec::new(),
+            store_tags: Vec::new(),
+            tree: None,
+            delete_tag: None,
+            tag_search: String::new(),
+            verify: None,
+            drop_blob: None,
+            seeds: Vec::new(),
+            node_id: None,
+            peer_input: String::new(),
+            relay_input: config.relay.to_string(),
+            bind_v4_input: config.bind_v4.to_string(),
+            bind_v6_input: config.bind_v6.to_string(),
+            replicas: BTreeMap::new(),
+            nearby: Vec::new(),
+            offers: Vec::new(),
+            connections: Vec::new(),
+            share_limit_kb: 0,
+            fetch_limit_kb: 0,
+            contacts: load_contacts(),
+            contact_name_input: String::new(),
+            contact_id_input: String::new(),
+            edit_tag: None,
+            gc_report: None,
+            fatal: None,
+            remove_old_store: true,
+            versions: Vec::new(),
+            watch: false,
             progress: ProgressList::new(),
             messages: Vec::new(),
-            config: config,
+            import_choice: config.import_choice,
+            ticket_type: config.ticket_type,
+            config,
+            ephemeral,
             elapsed: None,
         };
 
@@ -142,6 +443,71 @@ impl App {
         // Run the egui in the foreground, worker as  a subthread (async)
         eframe::run_native("sendme-egui", options, Box::new(|_cc| Ok(Box::new(app))))
     }
+
+    /// Seed without a window , the worker events go to the log.
+    pub fn headless() {
+        let config = load_config();
+        if config.ephemeral {
+            warn!("ephemeral is set in the config , seeding needs the store on disk");
+        }
+        let handle = Worker::spawn(config.store_path.clone(), false);
+        config.apply_limits(&handle.limits);
+        let contacts = load_contacts();
+        config.apply_senders(&handle.inbox, &contacts);
+        let commands = [
+            Command::Setup {
+                callback: Box::new(|| {}),
+            },
+            Command::GcSettings(config.gc_options()),
+            Command::NetSettings(config.net_options()),
+            config.replica_settings(),
+            Command::InboxSettings(config.inbox),
+        ];
+        for command in commands {
+            if handle.command_tx.send_blocking(command).is_err() {
+                return;
+            }
+        }
+        // Tickets are logged once per tag
+        let mut announced = HashSet::new();
+        // The event channel is bounded , keep draining it
+        while let Ok(event) = handle.event_rx.recv_blocking() {
+            match event {
+                Event::Message(message) => info!("{}", message),
+                Event::Fatal(text) => {
+                    error!("{text}");
+                    return;
+                }
+                // Nobody here to answer
+                Event::Offer(offer) => {
+                    info!(
+                        "declined {} from {} , offers are taken in the app",
+                        offer.offer.name,
+                        contacts.label(offer.from)
+                    );
+                    handle.inbox.answer(offer.id, false);
+                }
+                Event::Seeding(stats) if stats.is_empty() => {
+                    warn!("nothing is seeded , pick tags in the store browser");
+                }
+                Event::Seeding(stats) => {
+                    for seed in stats {
+                        if announced.insert(seed.tag.clone()) {
+                            info!("seeding {} with {}", seed.tag, seed.ticket);
+                        }
+                        info!(
+                            "{} , {} served , {} peers , {} requests",
+                            seed.tag,
+                            format_size(seed.bytes, DECIMAL),
+                            seed.peers.len(),
+                            seed.requests
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
 }
 
 // Actual gui code (the interface)
@@ -173,9 +539,50 @@ impl AppState {
                     self.elapsed = None;
                 }
                 Event::SendTicket(ticket) => self.send_ticket = Some(ticket),
+                Event::Shares(shares) => self.shares = shares,
+                Event::StoreTags(tags) => self.store_tags = tags,
+                Event::Tree(tree) => self.tree = Some(tree),
+                Event::GcReport(report) => self.gc_report = Some(report),
+                Event::Fatal(text) => self.fatal = Some(text),
+                Event::Verify(report) => self.verify = Some(report),
+                Event::BlobFixed(hash) => {
+                    if let Some(report) = &mut self.verify {
+                        report.damaged.retain(|damaged| damaged.hash != hash);
+                    }
+                }
+                Event::Seeding(stats) => self.seeds = stats,
+                Event::NodeId(node_id) => self.node_id = Some(node_id),
+                Event::Replica(status) => {
+                    self.replicas
+                        .insert((status.tag, status.peer), status.state);
+                }
+                Event::Nearby(peers) => self.nearby = peers,
+                Event::Offer(offer) => self.offers.push(offer),
+                Event::Connection(diag) => {
+                    if let Some(remote) = diag.remote
+                        && self.contacts.seen(diag.node_id, remote)
+                    {
+                        self.save_contacts();
+                    }
+                    match self.connections.iter_mut().find(|known| {
+                        known.node_id == diag.node_id && known.started == diag.started
+                    }) {
+                        Some(known) => *known = diag,
+                        None => self.connections.push(diag),
+                    }
+                }
+                Event::StoreMoved(path) => {
+                    self.config.store_path = path;
+                    self.save_config();
+                }
+                Event::ShareVersion(version) => self.versions.push(version),
+                Event::Preview(preview) => self.preview = Some(preview),
+                Event::DryRun(report) => self.dry_run = Some(report),
             }
         }
 
+        self.handle_drops(ctx);
+
         // active flags
         let mut send_enabled: bool = true;
 
@@ -192,7 +599,7 @@ impl AppState {
             AppMode::Finished => {
                 self.mode = AppMode::Idle;
             }
-            AppMode::Config => {
+            AppMode::Config | AppMode::Store | AppMode::Seed | AppMode::Contacts => {
                 send_enabled = false;
             }
         }
@@ -210,6 +617,19 @@ impl AppState {
                 if ui.button("Config").clicked() {
                     self.mode = AppMode::Config;
                 }
+                if !self.ephemeral && ui.button("Store").clicked() {
+                    self.tree = None;
+                    self.delete_tag = None;
+                    self.cmd(Command::ListStore);
+                    self.mode = AppMode::Store;
+                }
+                if !self.ephemeral && ui.button("Seeding").clicked() {
+                    self.cmd(Command::ListSeeds);
+                    self.mode = AppMode::Seed;
+                }
+                if ui.button("Contacts").clicked() {
+                    self.mode = AppMode::Contacts;
+                }
                 if ui.button("Cancel").clicked() {
                     self.cmd(Command::CancelTest);
                 }
@@ -230,14 +650,25 @@ impl AppState {
             // Main buttons
             ui.vertical_centered(|ui| ui.heading("Sendme"));
             ui.separator();
+            if let Some(text) = &self.fatal {
+                ui.colored_label(
+                    Color32::LIGHT_RED,
+                    format!("Stopped , {text} . Restart sendme."),
+                );
+                ui.separator();
+            }
             ui.add_space(5.);
             self.button_header(send_enabled, ui);
 
             ui.separator();
+            // Pushes from other nodes
+            self.show_offers(ui);
             // Modal Display
             self.modal_display(ui);
             // Show the current progress bars
             self.show_progress(ui);
+            // How the connections get through
+            self.show_connections(ui);
             // Show the current messages
             self.show_messages(ui);
         });
@@ -247,18 +678,52 @@ impl AppState {
         ui.horizontal(|ui| {
             ui.add_space(2.);
             ui.add_enabled_ui(send_enabled, |ui| {
-                if ui.button("Send Folder…").clicked() {
-                    if let Some(path) = rfd::FileDialog::new().pick_folder() {
-                        self.picked_path = Some(path);
-                    }
+                if ui.button("Send Folder…").clicked()
+                    && let Some(path) = rfd::FileDialog::new().pick_folder()
+                {
+                    self.picked_paths = vec![path];
+                    self.watch = false;
                     self.mode = AppMode::Send;
                 };
-                if ui.button("Send File…").clicked() {
-                    if let Some(path) = rfd::FileDialog::new().pick_file() {
-                        self.picked_path = Some(path);
-                    }
+                if ui
+                    .button("Watch Folder…")
+                    .on_hover_text("Serve a folder and publish a new version when it changes")
+                    .clicked()
+                    && let Some(path) = rfd::FileDialog::new().pick_folder()
+                {
+                    self.picked_paths = vec![path];
+                    self.watch = true;
                     self.mode = AppMode::Send;
                 };
+                if ui.button("Send File…").clicked()
+                    && let Some(path) = rfd::FileDialog::new().pick_file()
+                {
+                    self.picked_paths = vec![path];
+                    self.watch = false;
+                    self.mode = AppMode::Send;
+                };
+                if ui
+                    .button("Dry Run…")
+                    .on_hover_text("Check size, names and duplicates without importing")
+                    .clicked()
+                    && let Some(path) = rfd::FileDialog::new().pick_folder()
+                {
+                    self.picked_paths = vec![path];
+                    self.dry_run = None;
+                    self.cmd(Command::DryRun(self.picked_paths.clone()));
+                };
+                egui::ComboBox::from_id_salt("import_choice")
+                    .selected_text(format!("Import: {}", self.import_choice))
+                    .show_ui(ui, |ui| {
+                        for choice in ImportChoice::ALL {
+                            ui.selectable_value(
+                                &mut self.import_choice,
+                                choice,
+                                choice.to_string(),
+                            );
+                        }
+                    });
+                ticket_combo(ui, "ticket_type", &mut self.ticket_type, true);
             });
             // ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {});
         });
@@ -269,34 +734,72 @@ impl AppState {
         match self.mode {
             AppMode::Init => {}
             AppMode::Idle => {
+                self.staged_paths(ui);
                 self.fetch_box(ui);
+                self.previous_shares(ui);
             }
             AppMode::Send => {
-                if let Some(path) = &self.picked_path {
-                    self.cmd(Command::Send(path.to_owned()));
-                    self.mode = AppMode::SendProgress;
+                let paths = std::mem::take(&mut self.picked_paths);
+                self.dry_run = None;
+                if paths.is_empty() {
+                    self.mode = AppMode::Idle;
+                    return;
+                }
+                let options = SendOptions {
+                    import: self.import_choice,
+                    copy_threshold: self.config.copy_threshold_mb * 1024 * 1024,
+                    preserve_metadata: self.config.preserve_metadata,
+                    ticket_type: self.ticket_type,
+                    ephemeral: self.ephemeral,
+                    upload_limit: self.share_limit_kb * 1000,
+                };
+                self.send_ticket = None;
+                self.versions.clear();
+                if self.watch {
+                    self.share_name = Some(format!("Watching {}", paths[0].display()));
+                    self.cmd(Command::WatchShare((paths[0].clone(), options)));
+                } else {
+                    self.share_name = Some(match paths.as_slice() {
+                        [path] => format!("{}", path.display()),
+                        paths => format!("{} items", paths.len()),
+                    });
+                    self.cmd(Command::Send((paths, options)));
                 }
+                self.mode = AppMode::SendProgress;
             }
             AppMode::SendProgress => {
-                if let Some(path) = &self.picked_path {
-                    ui.label(format!("{}", path.display()));
+                if let Some(name) = &self.share_name {
+                    ui.label(name);
                 }
-                if let Some(ticket) = &self.send_ticket {
+                if let Some(ticket) = self.send_ticket.clone() {
                     ui.add_space(10.);
                     ui.label("Blob Ticket...");
                     ui.add_space(5.);
                     ui.separator();
-                    ui.label(RichText::new(ticket).font(FontId::monospace(15.)));
+                    egui::TextEdit::multiline(&mut ticket.as_str())
+                        .font(FontId::monospace(15.))
+                        .desired_width(f32::INFINITY)
+                        .show(ui);
+                    self.ticket_tools(ui, &ticket);
                     ui.separator();
                 }
 
+                self.version_history(ui);
+
+                if limit_value(ui, "Upload limit", &mut self.share_limit_kb) {
+                    self.worker.limits.share.set(self.share_limit_kb * 1000);
+                }
                 if ui.button("Finish").clicked() {
-                    // TODO Send cancel token to worker for send
-                    // clean and reset interfaces
+                    // Stop serving , the worker says when it is done
+                    self.cmd(Command::StopShare);
+                    self.send_ticket = None;
                 }
             }
             AppMode::FetchProgess => {
                 ui.label("Fetching ...");
+                if limit_value(ui, "Download limit", &mut self.fetch_limit_kb) {
+                    self.worker.limits.fetch.set(self.fetch_limit_kb * 1000);
+                }
             }
             AppMode::Finished => {
                 // self.reset();
@@ -306,44 +809,1429 @@ impl AppState {
                 ui.label("Configuration");
                 ui.checkbox(&mut self.config.dark_mode, "Darkmode");
                 ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("Default import");
+                    egui::ComboBox::from_id_salt("config_import_choice")
+                        .selected_text(self.config.import_choice.to_string())
+                        .show_ui(ui, |ui| {
+                            for choice in ImportChoice::ALL {
+                                ui.selectable_value(
+                                    &mut self.config.import_choice,
+                                    choice,
+                                    choice.to_string(),
+                                );
+                            }
+                        });
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Copy files under");
+                    ui.add(egui::DragValue::new(&mut self.config.copy_threshold_mb).suffix(" MB"));
+                });
+                ui.small("Referenced files are served in place and must not change while shared.");
+                ui.horizontal(|ui| {
+                    ui.label("Default ticket");
+                    ticket_combo(
+                        ui,
+                        "config_ticket_type",
+                        &mut self.config.ticket_type,
+                        false,
+                    );
+                });
+                ui.small(
+                    "Id only tickets are short but need discovery, full tickets work on a LAN.",
+                );
+                ui.checkbox(
+                    &mut self.config.preserve_metadata,
+                    "Send file times, permissions and empty folders",
+                );
+                ui.separator();
+                self.net_config(ui);
+                ui.separator();
+                ui.label("Blob store");
+                ui.checkbox(
+                    &mut self.config.ephemeral,
+                    "Ephemeral , keep nothing on disk",
+                )
+                .on_hover_text("Takes effect on the next start , same as --ephemeral");
+                if self.ephemeral {
+                    ui.small("Running in memory , everything is gone on exit.");
+                } else {
+                    self.store_config(ui);
+                    ui.separator();
+                    self.replica_config(ui);
+                    ui.separator();
+                    self.inbox_config(ui);
+                }
+                ui.separator();
                 if ui.button("Save Config").clicked() {
+                    self.import_choice = self.config.import_choice;
+                    self.ticket_type = self.config.ticket_type;
+                    self.save_config();
+                    self.cmd(Command::GcSettings(self.config.gc_options()));
+                    self.cmd(Command::NetSettings(self.config.net_options()));
+                    self.cmd(self.config.replica_settings());
+                    self.cmd(Command::InboxSettings(self.config.inbox));
+                    self.apply_senders();
+                    self.config.apply_limits(&self.worker.limits);
                     self.mode = AppMode::Idle;
                 }
             }
+            AppMode::Store => self.store_browser(ui),
+            AppMode::Seed => self.seed_dashboard(ui),
+            AppMode::Contacts => self.contacts_page(ui),
+        }
+    }
+
+    // Relays for every connection
+    fn net_config(&mut self, ui: &mut Ui) {
+        ui.label("Network");
+        ui.horizontal(|ui| {
+            ui.label("Relay");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.relay_input)
+                    .hint_text("default , disabled or a url"),
+            );
+            if ui.small_button("Default").clicked() {
+                self.relay_input = RelayModeOption::Default.to_string();
+            }
+            if ui.small_button("Disabled").clicked() {
+                self.relay_input = RelayModeOption::Disabled.to_string();
+            }
+        });
+        match RelayModeOption::from_str(self.relay_input.trim()) {
+            Ok(relay) => self.config.relay = relay,
+            Err(_) => {
+                ui.colored_label(Color32::LIGHT_RED, "not a relay url");
+            }
+        }
+        ui.small("Disable relays on a LAN without internet. New connections use the change.");
+        ui.horizontal(|ui| {
+            ui.label("IPv4 bind");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.bind_v4_input)
+                    .hint_text("0.0.0.0:0")
+                    .desired_width(160.),
+            );
+            match SocketAddrV4::from_str(self.bind_v4_input.trim()) {
+                Ok(addr) => self.config.bind_v4 = addr,
+                Err(_) => {
+                    ui.colored_label(Color32::LIGHT_RED, "not an address:port");
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("IPv6 bind");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.bind_v6_input)
+                    .hint_text("[::]:0")
+                    .desired_width(160.),
+            );
+            match SocketAddrV6::from_str(self.bind_v6_input.trim()) {
+                Ok(addr) => self.config.bind_v6 = addr,
+                Err(_) => {
+                    ui.colored_label(Color32::LIGHT_RED, "not an address:port");
+                }
+            }
+        });
+        ui.small(
+            "Port 0 is random. Fixed ports go to whichever of the seeding node and a share starts first , the other takes a random port.",
+        );
+        ui.horizontal(|ui| {
+            ui.label("Upload limit");
+            ui.add(egui::DragValue::new(&mut self.config.upload_limit_kb).suffix(" kB/s"));
+            ui.label("Download limit");
+            ui.add(egui::DragValue::new(&mut self.config.download_limit_kb).suffix(" kB/s"));
+        });
+        ui.small(
+            "Zero is no limit. Shared by every transfer , a share or fetch can go lower. \
+             A download can run about a megabyte ahead of its limit at the start.",
+        );
+        ui.checkbox(
+            &mut self.config.local_discovery,
+            "Find nearby devices on the local network",
+        )
+        .on_hover_text("mdns , id only tickets work without internet");
+        if self.config.local_discovery {
+            ui.horizontal(|ui| {
+                ui.label("Device name");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.config.device_name)
+                        .hint_text("empty stays hidden")
+                        .char_limit(64),
+                );
+            });
+            self.nearby_list(ui);
+        }
+    }
+
+    // Named nodes seen over mdns , they can be taken as replication peers
+    fn nearby_list(&mut self, ui: &mut Ui) {
+        if self.nearby.is_empty() {
+            ui.small("No nearby devices yet , they show once this node runs.");
+            return;
+        }
+        ui.label("Nearby devices");
+        let mut add = None;
+        egui::Grid::new("nearby_grid").striped(true).show(ui, |ui| {
+            for peer in &self.nearby {
+                let node_id = peer.node_id.to_string();
+                match self.contacts.name(peer.node_id) {
+                    Some(contact) if contact != peer.name => {
+                        ui.label(format!("{} ({})", peer.name, contact));
+                    }
+                    _ => {
+                        ui.label(peer.name.as_str());
+                    }
+                }
+                ui.small(peer.node_id.fmt_short()).on_hover_text(&node_id);
+                let ago = peer.seen.elapsed().unwrap_or_default().as_secs();
+                ui.small(format!("{} s ago", ago));
+                if ui.small_button("Copy").clicked() {
+                    ui.ctx().copy_text(node_id.clone());
+                }
+                let known = self.config.replica_peers.contains(&node_id);
+                if ui
+                    .add_enabled(!known, egui::Button::new("Add peer").small())
+                    .clicked()
+                {
+                    add = Some(node_id);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(node_id) = add {
+            self.config.replica_peers.push(node_id);
+        }
+    }
+
+    // Limits and location of the blob store on disk
+    fn store_config(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Max size");
+            ui.add(egui::DragValue::new(&mut self.config.store_max_gb).suffix(" GB"));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Remove fetched after");
+            ui.add(egui::DragValue::new(&mut self.config.incoming_expiry_days).suffix(" days"));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Clean up every");
+            ui.add(egui::DragValue::new(&mut self.config.gc_interval_hours).suffix(" hours"));
+        });
+        ui.small("Zero turns a limit off. Pinned and seeded collections are always kept.");
+        ui.horizontal(|ui| {
+            ui.label("Location");
+            ui.small(format!("{}", self.config.store_path.display()));
+        });
+        ui.horizontal(|ui| {
+            if ui
+                .button("Move Store…")
+                .on_hover_text("Stops sharing, copies the store and checks the copy")
+                .clicked()
+                && let Some(folder) = rfd::FileDialog::new().pick_folder()
+            {
+                let name = self
+                    .config
+                    .store_path
+                    .file_name()
+                    .map(|name| name.to_owned())
+                    .unwrap_or_else(|| "blob_data".into());
+                self.cmd(Command::MoveStore((
+                    folder.join(name),
+                    self.remove_old_store,
+                )));
+            }
+            ui.checkbox(&mut self.remove_old_store, "Remove the old copy");
+        });
+    }
+
+    // Peer nodes that keep copies of the shares
+    fn replica_config(&mut self, ui: &mut Ui) {
+        ui.label("Replication");
+        ui.horizontal(|ui| {
+            ui.label("This node");
+            match self.node_id {
+                Some(node_id) => {
+                    ui.small(node_id.fmt_short())
+                        .on_hover_text(node_id.to_string());
+                    if ui.small_button("Copy").clicked() {
+                        ui.ctx().copy_text(node_id.to_string());
+                    }
+                }
+                None => {
+                    ui.small("starts with the first peer , seed or local discovery");
+                }
+            }
+        });
+        let mut remove = None;
+        for (i, peer) in self.config.replica_peers.iter().enumerate() {
+            ui.horizontal(|ui| {
+                match NodeId::from_str(peer)
+                    .ok()
+                    .and_then(|id| self.contacts.name(id))
+                {
+                    Some(name) => ui.small(name).on_hover_text(peer.as_str()),
+                    None => ui.small(peer.as_str()),
+                };
+                if ui.small_button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.config.replica_peers.remove(i);
+        }
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.peer_input).hint_text("peer node id"));
+            let peer = self.peer_input.trim().to_string();
+            let valid = NodeId::from_str(&peer).is_ok();
+            if ui.add_enabled(valid, egui::Button::new("Add")).clicked() {
+                if !self.config.replica_peers.contains(&peer) {
+                    self.config.replica_peers.push(peer);
+                }
+                self.peer_input.clear();
+            } else if !peer.is_empty() && !valid {
+                ui.colored_label(Color32::LIGHT_RED, "not a node id");
+            }
+        });
+        ui.checkbox(
+            &mut self.config.replicate_shares,
+            "Push new shares to the peers",
+        );
+        ui.small("Peers must list this node too. Replicas are never expired.");
+    }
+
+    fn inbox_config(&mut self, ui: &mut Ui) {
+        ui.label("Inbox");
+        ui.checkbox(
+            &mut self.config.inbox,
+            "Take collections pushed by other nodes",
+        )
+        .on_hover_text("Keeps the seeding node up , senders push to its id");
+        ui.add_enabled(
+            self.config.inbox,
+            egui::Checkbox::new(
+                &mut self.config.inbox_contacts_only,
+                "Decline senders not in the contacts",
+            ),
+        );
+    }
+
+    // Pushes waiting for an answer , accepting fetches like a ticket
+    fn show_offers(&mut self, ui: &mut Ui) {
+        if self.offers.is_empty() {
+            return;
+        }
+        let mut answered = None;
+        for offer in &self.offers {
+            ui.horizontal(|ui| {
+                ui.colored_label(
+                    Color32::LIGHT_BLUE,
+                    format!(
+                        "{} offers {} , {}",
+                        self.contacts.label(offer.from),
+                        offer.offer.name,
+                        format_size(offer.offer.size, DECIMAL)
+                    ),
+                )
+                .on_hover_text(offer.from.to_string());
+                if ui
+                    .add_enabled(self.mode == AppMode::Idle, egui::Button::new("Accept"))
+                    .on_disabled_hover_text("Finish the current job first")
+                    .clicked()
+                {
+                    answered = Some((offer.clone(), true));
+                }
+                if ui.button("Decline").clicked() {
+                    answered = Some((offer.clone(), false));
+                }
+            });
+        }
+        ui.separator();
+        let Some((offer, accept)) = answered else {
+            return;
+        };
+        self.offers.retain(|known| known.id != offer.id);
+        if !self.worker.inbox.answer(offer.id, accept) {
+            warn!("offer of {} is no longer waiting", offer.offer.name);
+            return;
+        }
+        if accept {
+            self.ticket_note = Some(format!("Pushed by {}", self.contacts.label(offer.from)));
+            self.receiver_ticket = offer.ticket.clone();
+            self.preview = None;
+            self.cmd(Command::Fetch((
+                offer.ticket,
+                self.config.download_path.clone(),
+                self.fetch_options,
+            )));
+            self.mode = AppMode::FetchProgess;
+        }
+    }
+
+    // Every tag in the blob store with things to do to it
+    fn store_browser(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "Blob store , {} tags , {}",
+                self.store_tags.len(),
+                format_size(
+                    self.store_tags.iter().map(|entry| entry.size).sum::<u64>(),
+                    DECIMAL
+                )
+            ));
+            if ui.small_button("Refresh").clicked() {
+                self.cmd(Command::ListStore);
+            }
+            if ui.small_button("Clean Up").clicked() {
+                self.cmd(Command::CollectGarbage);
+            }
+            if ui
+                .small_button("Verify")
+                .on_hover_text("Read every blob back and check it")
+                .clicked()
+            {
+                self.verify = None;
+                self.cmd(Command::VerifyStore);
+            }
+            if ui.small_button("Back").clicked() {
+                self.mode = AppMode::Idle;
+            }
+        });
+        if let Some(report) = &self.gc_report {
+            ui.small(format!(
+                "Last clean up , {} reclaimed , {} expired , {} over quota",
+                format_size(report.reclaimed(), DECIMAL),
+                report.expired,
+                report.evicted
+            ));
+        }
+        self.verify_report(ui);
+        ui.horizontal(|ui| {
+            ui.label("Search");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.tag_search)
+                    .hint_text("name , note , hash or date"),
+            );
+            if !self.tag_search.is_empty() && ui.small_button("Clear").clicked() {
+                self.tag_search.clear();
+            }
+        });
+        ui.add_space(5.);
+        let mut action = None;
+        egui::ScrollArea::vertical()
+            .id_salt("store_scroll")
+            .max_height(250.)
+            .show(ui, |ui| {
+                egui::Grid::new("store_grid")
+                    .num_columns(6)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for entry in self
+                            .store_tags
+                            .iter()
+                            .filter(|entry| entry.matches(&self.tag_search))
+                        {
+                            let mut hover = format!("{}\n{}", entry.tag, entry.hash);
+                            if !entry.note.is_empty() {
+                                hover = format!("{}\n\n{}", hover, entry.note);
+                            }
+                            ui.vertical(|ui| {
+                                ui.label(entry.label()).on_hover_text(hover);
+                                if !entry.note.is_empty() {
+                                    ui.small(entry.note.lines().next().unwrap_or_default());
+                                }
+                            });
+                            ui.label(format!("{} files", entry.files));
+                            ui.label(format_size(entry.size, DECIMAL));
+                            if entry.complete {
+                                ui.label("complete");
+                            } else {
+                                ui.colored_label(Color32::LIGHT_RED, "partial");
+                            }
+                            match entry.created {
+                                Some(date) => ui.label(date.format("%Y-%m-%d %H:%M").to_string()),
+                                None => ui.label(""),
+                            };
+                            ui.horizontal(|ui| {
+                                let pin = if entry.pinned { "Unpin" } else { "Pin" };
+                                if ui
+                                    .small_button(pin)
+                                    .on_hover_text("Pinned collections are never cleaned up")
+                                    .clicked()
+                                {
+                                    action = Some(StoreAction::Pin(entry.clone()));
+                                }
+                                if ui.small_button("Edit").clicked() {
+                                    action = Some(StoreAction::Edit(entry.clone()));
+                                }
+                                if ui.small_button("Files").clicked() {
+                                    action = Some(StoreAction::Inspect(entry.hash));
+                                }
+                                ui.add_enabled_ui(entry.complete, |ui| {
+                                    let seed = if entry.seeded { "Unseed" } else { "Seed" };
+                                    if ui
+                                        .small_button(seed)
+                                        .on_hover_text(
+                                            "Serve this collection whenever the app runs",
+                                        )
+                                        .clicked()
+                                    {
+                                        action = Some(StoreAction::Seed(entry.clone()));
+                                    }
+                                    if !self.config.replica_peers.is_empty()
+                                        && ui
+                                            .small_button("Replicate")
+                                            .on_hover_text("Push a copy to the replication peers")
+                                            .clicked()
+                                    {
+                                        action = Some(StoreAction::Replicate(entry.clone()));
+                                    }
+                                    if !self.contacts.is_empty() && entry.complete {
+                                        ui.menu_button("Push…", |ui| {
+                                            for contact in self.contacts.iter() {
+                                                if ui.button(&contact.name).clicked() {
+                                                    action = Some(StoreAction::Push((
+                                                        entry.clone(),
+                                                        contact.node_id,
+                                                    )));
+                                                    ui.close();
+                                                }
+                                            }
+                                        })
+                                        .response
+                                        .on_hover_text("Offer the collection to a contact");
+                                    }
+                                    if ui.small_button("Export…").clicked() {
+                                        action = Some(StoreAction::Export(entry.hash));
+                                    }
+                                    if ui.small_button("Share").clicked() {
+                                        action = Some(StoreAction::Share(entry.clone()));
+                                    }
+                                    if ui
+                                        .small_button("Bundle…")
+                                        .on_hover_text("Write the collection to one file")
+                                        .clicked()
+                                    {
+                                        action = Some(StoreAction::Bundle(entry.clone()));
+                                    }
+                                });
+                                if self.delete_tag.as_ref() == Some(&entry.tag) {
+                                    let sure = egui::Button::new(
+                                        egui::RichText::new("Sure?").color(Color32::LIGHT_RED),
+                                    )
+                                    .small();
+                                    if ui.add(sure).clicked() {
+                                        action = Some(StoreAction::Delete(entry.tag.clone()));
+                                    }
+                                } else if ui.small_button("Delete").clicked() {
+                                    self.delete_tag = Some(entry.tag.clone());
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+            });
+        match action {
+            Some(StoreAction::Inspect(hash)) => {
+                self.tree = None;
+                self.cmd(Command::InspectTag(hash));
+            }
+            Some(StoreAction::Export(hash)) => {
+                if let Some(path) = rfd::FileDialog::new()
+                    .set_directory(&self.config.download_path)
+                    .pick_folder()
+                {
+                    self.cmd(Command::ExportTag((hash, path)));
+                }
+            }
+            Some(StoreAction::Bundle(entry)) => {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("sendme bundle", &[BUNDLE_EXTENSION])
+                    .set_directory(&self.config.download_path)
+                    .set_file_name(format!("{}.{}", entry.name, BUNDLE_EXTENSION))
+                    .save_file()
+                {
+                    self.cmd(Command::ExportBundle((entry.hash, path)));
+                }
+            }
+            Some(StoreAction::Share(entry)) => {
+                self.share_name = Some(entry.name);
+                self.send_ticket = None;
+                self.versions.clear();
+                self.cmd(Command::Reshare((entry.hash, self.ticket_type)));
+                self.mode = AppMode::SendProgress;
+            }
+            Some(StoreAction::Pin(entry)) if entry.pinned => {
+                self.cmd(Command::UnpinTag(entry.tag));
+            }
+            Some(StoreAction::Pin(entry)) => {
+                self.cmd(Command::PinTag((entry.tag, entry.hash)));
+            }
+            Some(StoreAction::Seed(entry)) if entry.seeded => {
+                self.cmd(Command::UnseedTag(entry.tag));
+            }
+            Some(StoreAction::Seed(entry)) => {
+                self.cmd(Command::SeedTag((entry.tag, entry.hash)));
+            }
+            Some(StoreAction::Replicate(entry)) => {
+                self.cmd(Command::Replicate((entry.tag, entry.hash)));
+            }
+            Some(StoreAction::Push((entry, node_id))) => {
+                let offer = Offer {
+                    hash: entry.hash,
+                    name: entry.name,
+                    size: entry.size,
+                };
+                self.cmd(Command::Push((self.contacts.node_addr(node_id), offer)));
+            }
+            Some(StoreAction::Edit(entry)) => {
+                self.edit_tag = Some(TagEdit {
+                    tag: entry.tag.clone(),
+                    direction: entry.direction(),
+                    label: entry.label().to_string(),
+                    old_label: entry.label().to_string(),
+                    note: entry.note.clone(),
+                    old_note: entry.note,
+                });
+            }
+            Some(StoreAction::Delete(tag)) => {
+                self.delete_tag = None;
+                self.tree = None;
+                self.cmd(Command::DeleteTag(tag));
+            }
+            None => {}
+        }
+        self.tag_editor(ui);
+        self.replica_status(ui);
+        self.collection_tree(ui);
+    }
+
+    // Pushes to the peers since the start
+    fn replica_status(&mut self, ui: &mut Ui) {
+        if self.replicas.is_empty() {
+            return;
+        }
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Replication");
+            if ui.small_button("Clear").clicked() {
+                self.replicas
+                    .retain(|_, state| matches!(state, ReplicaState::Running));
+            }
+        });
+        egui::Grid::new("replica_grid")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                for ((tag, peer), state) in &self.replicas {
+                    ui.label(tag.as_str());
+                    ui.label(self.contacts.label(*peer))
+                        .on_hover_text(peer.to_string());
+                    match state {
+                        ReplicaState::Running => {
+                            ui.spinner();
+                        }
+                        ReplicaState::Done(bytes) => {
+                            ui.colored_label(
+                                Color32::LIGHT_GREEN,
+                                format!("done , {} fetched", format_size(*bytes, DECIMAL)),
+                            );
+                        }
+                        ReplicaState::Failed(err) => {
+                            ui.colored_label(Color32::LIGHT_RED, "failed")
+                                .on_hover_text(err);
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+    }
+
+    // The address book , names for the node ids we know
+    fn contacts_page(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label(format!("Contacts , {}", self.contacts.len()));
+            if ui.small_button("Import…").clicked()
+                && let Some(path) = rfd::FileDialog::new()
+                    .add_filter("contacts", &["json"])
+                    .pick_file()
+            {
+                match self.contacts.import(&path) {
+                    Ok(added) => {
+                        info!("{} new contacts from {}", added, path.display());
+                        self.save_contacts();
+                    }
+                    Err(err) => warn!("failed to import contacts {err}"),
+                }
+            }
+            if ui.small_button("Export…").clicked()
+                && let Some(path) = rfd::FileDialog::new()
+                    .add_filter("contacts", &["json"])
+                    .set_file_name("contacts.json")
+                    .save_file()
+                && let Err(err) = self.contacts.save(&path)
+            {
+                warn!("failed to export contacts {err}");
+            }
+            if ui.small_button("Back").clicked() {
+                self.mode = AppMode::Idle;
+            }
+        });
+        ui.add_space(5.);
+        let mut changed = false;
+        let mut remove = None;
+        egui::ScrollArea::vertical()
+            .id_salt("contact_scroll")
+            .max_height(300.)
+            .show(ui, |ui| {
+                egui::Grid::new("contact_grid")
+                    .num_columns(4)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for contact in self.contacts.iter_mut() {
+                            let name = ui.add(
+                                egui::TextEdit::singleline(&mut contact.name).desired_width(120.),
+                            );
+                            changed |= name.lost_focus();
+                            ui.small(contact.node_id.fmt_short())
+                                .on_hover_text(contact.node_id.to_string());
+                            let seen = contact
+                                .addrs
+                                .iter()
+                                .map(|addr| addr.to_string())
+                                .collect::<Vec<_>>();
+                            match seen.first() {
+                                Some(addr) => ui.small(addr).on_hover_text(seen.join("\n")),
+                                None => ui.small("not seen yet"),
+                            };
+                            ui.horizontal(|ui| {
+                                if ui.small_button("Copy").clicked() {
+                                    ui.ctx().copy_text(contact.node_id.to_string());
+                                }
+                                if ui.small_button("Remove").clicked() {
+                                    remove = Some(contact.node_id);
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+            });
+        if self.contacts.is_empty() {
+            ui.small("No contacts yet , add one or import a book.");
+        }
+        if let Some(node_id) = remove {
+            self.contacts.remove(node_id);
+            changed = true;
+        }
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.contact_name_input)
+                    .hint_text("name")
+                    .desired_width(100.),
+            );
+            ui.add(egui::TextEdit::singleline(&mut self.contact_id_input).hint_text("node id"));
+            let name = self.contact_name_input.trim();
+            let node_id = NodeId::from_str(self.contact_id_input.trim());
+            let valid = !name.is_empty() && node_id.is_ok();
+            if ui.add_enabled(valid, egui::Button::new("Add")).clicked()
+                && let Ok(node_id) = node_id
+            {
+                self.contacts.add(name, node_id);
+                self.contact_name_input.clear();
+                self.contact_id_input.clear();
+                changed = true;
+            }
+        });
+        ui.small("Names show wherever the node turns up. Addresses are noted on each connection.");
+        if let Some(node_id) = self.node_id {
+            ui.horizontal(|ui| {
+                ui.small(format!("This node {}", node_id.fmt_short()))
+                    .on_hover_text("Contacts push to this id");
+                if ui.small_button("Copy").clicked() {
+                    ui.ctx().copy_text(node_id.to_string());
+                }
+            });
+        }
+        if changed {
+            self.save_contacts();
+        }
+    }
+
+    // What the seeding node has served since the start
+    fn seed_dashboard(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "Seeding {} collections , {} served",
+                self.seeds.len(),
+                format_size(
+                    self.seeds.iter().map(|seed| seed.bytes).sum::<u64>(),
+                    DECIMAL
+                )
+            ));
+            if ui.small_button("Refresh").clicked() {
+                self.cmd(Command::ListSeeds);
+            }
+            if ui.small_button("Back").clicked() {
+                self.mode = AppMode::Idle;
+            }
+        });
+        if self.seeds.is_empty() {
+            ui.small("Nothing is seeded , use Seed in the store browser.");
+            return;
+        }
+        ui.add_space(5.);
+        let mut unseed = None;
+        egui::ScrollArea::vertical()
+            .id_salt("seed_scroll")
+            .max_height(250.)
+            .show(ui, |ui| {
+                egui::Grid::new("seed_grid")
+                    .num_columns(5)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("Tag");
+                        ui.strong("Served");
+                        ui.strong("Peers");
+                        ui.strong("Requests");
+                        ui.label("");
+                        ui.end_row();
+                        for seed in &self.seeds {
+                            ui.label(&seed.tag).on_hover_text(seed.hash.to_string());
+                            ui.label(format_size(seed.bytes, DECIMAL));
+                            let names = seed
+                                .peers
+                                .iter()
+                                .map(|peer| self.contacts.label(*peer))
+                                .collect::<Vec<_>>();
+                            ui.label(seed.peers.len().to_string())
+                                .on_hover_text(names.join("\n"));
+                            ui.label(seed.requests.to_string());
+                            ui.horizontal(|ui| {
+                                if ui.small_button("Copy Ticket").clicked() {
+                                    ui.ctx().copy_text(seed.ticket.clone());
+                                }
+                                if ui.small_button("Stop").clicked() {
+                                    unseed = Some(seed.tag.clone());
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+            });
+        if let Some(tag) = unseed {
+            self.cmd(Command::UnseedTag(tag));
+        }
+    }
+
+    // Damaged blobs from the last store check
+    fn verify_report(&mut self, ui: &mut Ui) {
+        let Some(report) = &self.verify else {
+            return;
+        };
+        if report.damaged.is_empty() {
+            ui.small(format!(
+                "Last check , {} blobs , {} , all good",
+                report.checked,
+                format_size(report.bytes, DECIMAL)
+            ));
+            return;
+        }
+        ui.colored_label(
+            Color32::LIGHT_RED,
+            format!("{} damaged blobs", report.damaged.len()),
+        );
+        let mut command = None;
+        egui::Grid::new("damaged_grid")
+            .num_columns(4)
+            .striped(true)
+            .show(ui, |ui| {
+                for damaged in report.damaged.iter() {
+                    ui.label(damaged.hash.fmt_short().to_string())
+                        .on_hover_text(&damaged.error);
+                    ui.label(format_size(damaged.size, DECIMAL));
+                    match &damaged.source {
+                        Some(source) => ui.label(source.display().to_string()),
+                        None => ui.label("in store"),
+                    };
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(
+                                damaged.source.is_some(),
+                                egui::Button::new("Re-import").small(),
+                            )
+                            .on_hover_text("Copy the original file into the store again")
+                            .clicked()
+                        {
+                            command = Some(Command::RepairBlob(damaged.hash));
+                        }
+                        let hover = if damaged.tags.is_empty() {
+                            "Not in any collection".to_string()
+                        } else {
+                            format!("Also removes {}", damaged.tags.join(" , "))
+                        };
+                        if self.drop_blob == Some(damaged.hash) {
+                            let sure = egui::Button::new(
+                                egui::RichText::new("Sure?").color(Color32::LIGHT_RED),
+                            )
+                            .small();
+                            if ui.add(sure).on_hover_text(hover).clicked() {
+                                command = Some(Command::DropBlob(damaged.hash));
+                            }
+                        } else if ui.small_button("Drop").on_hover_text(hover).clicked() {
+                            self.drop_blob = Some(damaged.hash);
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        if let Some(command) = command {
+            self.drop_blob = None;
+            self.cmd(command);
         }
     }
 
+    // Rename a tag and edit its note
+    fn tag_editor(&mut self, ui: &mut Ui) {
+        let Some(edit) = &mut self.edit_tag else {
+            return;
+        };
+        let mut done = false;
+        let mut commands = Vec::new();
+        ui.add_space(5.);
+        ui.group(|ui| {
+            egui::Grid::new("tag_edit_grid")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Name");
+                    ui.horizontal(|ui| {
+                        if let Some(direction) = edit.direction {
+                            ui.label(format!("{direction}/"));
+                        }
+                        ui.text_edit_singleline(&mut edit.label);
+                    });
+                    ui.end_row();
+                    ui.label("Note");
+                    ui.add(egui::TextEdit::multiline(&mut edit.note).desired_rows(3));
+                    ui.end_row();
+                });
+            ui.horizontal(|ui| {
+                let label = edit.label.trim();
+                let name = match edit.direction {
+                    Some(direction) => format!("{direction}/{label}"),
+                    None => label.to_string(),
+                };
+                if ui
+                    .add_enabled(!label.is_empty(), egui::Button::new("Save"))
+                    .clicked()
+                {
+                    // The note goes first , it moves with the rename
+                    if edit.note != edit.old_note {
+                        commands.push(Command::TagNote((edit.tag.clone(), edit.note.clone())));
+                    }
+                    if label != edit.old_label {
+                        commands.push(Command::RenameTag((edit.tag.clone(), name)));
+                    }
+                    done = true;
+                }
+                if ui.button("Cancel").clicked() {
+                    done = true;
+                }
+            });
+        });
+        for command in commands {
+            self.cmd(command);
+        }
+        if done {
+            self.edit_tag = None;
+        }
+    }
+
+    // Files of the collection picked in the store browser
+    fn collection_tree(&mut self, ui: &mut Ui) {
+        let Some((hash, items)) = &self.tree else {
+            return;
+        };
+        ui.add_space(5.);
+        egui::CollapsingHeader::new(format!("{} , {} files", hash.fmt_short(), items.len()))
+            .id_salt("store_tree")
+            .default_open(true)
+            .show(ui, |ui| {
+                egui::ScrollArea::vertical()
+                    .id_salt("store_tree_scroll")
+                    .max_height(150.)
+                    .show(ui, |ui| {
+                        egui::Grid::new("store_tree_grid")
+                            .num_columns(2)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for item in items.iter() {
+                                    ui.label(&item.name).on_hover_text(item.hash.to_string());
+                                    match item.size {
+                                        Some(size) => ui.label(format_size(size, DECIMAL)),
+                                        None => ui.colored_label(Color32::LIGHT_RED, "missing"),
+                                    };
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            });
+    }
+
     fn fetch_box(&mut self, ui: &mut Ui) {
         ui.label("Fetch blob with ticket...");
         ui.add_space(8.);
         let _ticket_edit = egui::TextEdit::multiline(&mut self.receiver_ticket)
             .desired_width(f32::INFINITY)
             .show(ui);
+        if let Some(note) = &self.ticket_note {
+            ui.small(note);
+        }
+        self.ticket_sender(ui);
         ui.add_space(5.);
         ui.horizontal(|ui| {
+            if ui.button("Open Ticket…").clicked()
+                && let Some(path) = rfd::FileDialog::new()
+                    .add_filter("sendme ticket", &[TICKET_EXTENSION])
+                    .pick_file()
+            {
+                match TicketFile::load(&path) {
+                    Ok(file) => {
+                        self.receiver_ticket = file.ticket;
+                        self.ticket_note =
+                            (!file.description.is_empty()).then_some(file.description);
+                    }
+                    Err(err) => warn!("failed to read ticket {err}"),
+                }
+            }
+            if ui.button("Preview").clicked() {
+                self.preview = None;
+                self.cmd(Command::Preview(self.ticket_to_fetch()));
+            }
             if ui.button("Fetch").clicked() {
                 self.cmd(Command::Fetch((
-                    self.receiver_ticket.clone(),
+                    self.ticket_to_fetch(),
                     self.config.download_path.clone(),
+                    self.fetch_options,
                 )));
                 self.mode = AppMode::FetchProgess;
             };
-            if ui.button("Fetch Into...").clicked() {
-                if let Some(path) = rfd::FileDialog::new().pick_folder() {
-                    self.picked_path = Some(path.clone());
-                    self.cmd(Command::Fetch((self.receiver_ticket.clone(), path.clone())));
-                    self.mode = AppMode::FetchProgess;
-                }
+            if ui.button("Fetch Into...").clicked()
+                && let Some(path) = rfd::FileDialog::new().pick_folder()
+            {
+                self.cmd(Command::Fetch((
+                    self.ticket_to_fetch(),
+                    path.clone(),
+                    self.fetch_options,
+                )));
+                self.mode = AppMode::FetchProgess;
             };
+            if ui
+                .button("Import Bundle…")
+                .on_hover_text("Fetch from a bundle file instead of the network")
+                .clicked()
+                && let Some(path) = rfd::FileDialog::new()
+                    .add_filter("sendme bundle", &[BUNDLE_EXTENSION])
+                    .pick_file()
+            {
+                self.cmd(Command::ImportBundle((
+                    path,
+                    self.config.download_path.clone(),
+                    self.fetch_options,
+                )));
+                self.mode = AppMode::FetchProgess;
+            }
         });
+        if !self.ephemeral {
+            let mut drop_after = !self.fetch_options.keep_in_store;
+            ui.checkbox(&mut drop_after, "Don't keep in store after export")
+                .on_hover_text("Moves the files out of the store where the disk allows it");
+            self.fetch_options.keep_in_store = !drop_after;
+        }
+        limit_value(ui, "Download limit", &mut self.fetch_limit_kb);
+        self.fetch_options.download_limit = self.fetch_limit_kb * 1000;
+        self.show_preview(ui);
+    }
+
+    // Names and sizes behind the ticket in the fetch box
+    fn show_preview(&mut self, ui: &mut Ui) {
+        let Some(preview) = &self.preview else {
+            return;
+        };
+        ui.add_space(5.);
+        egui::CollapsingHeader::new(format!(
+            "{} files , {}",
+            preview.files.len(),
+            format_size(preview.total, DECIMAL)
+        ))
+        .id_salt("fetch_preview")
+        .default_open(true)
+        .show(ui, |ui| {
+            ui.small(format!("collection {}", preview.hash.fmt_short()));
+            egui::ScrollArea::vertical()
+                .id_salt("fetch_preview_scroll")
+                .max_height(150.)
+                .show(ui, |ui| {
+                    egui::Grid::new("fetch_preview_grid")
+                        .num_columns(2)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (name, size) in preview.files.iter() {
+                                ui.label(name);
+                                ui.label(format_size(*size, DECIMAL));
+                                ui.end_row();
+                            }
+                        });
+                });
+        });
+    }
+
+    // Files and folders dropped on the window, waiting to be sent
+    fn staged_paths(&mut self, ui: &mut Ui) {
+        if self.picked_paths.is_empty() {
+            return;
+        }
+        ui.label("Ready to send...");
+        for path in self.picked_paths.iter() {
+            ui.small(format!("{}", path.display()));
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Send").clicked() {
+                self.watch = false;
+                self.mode = AppMode::Send;
+            }
+            if ui.button("Dry Run").clicked() {
+                self.dry_run = None;
+                self.cmd(Command::DryRun(self.picked_paths.clone()));
+            }
+            if ui.button("Clear").clicked() {
+                self.picked_paths.clear();
+                self.dry_run = None;
+            }
+        });
+        limit_value(ui, "Upload limit", &mut self.share_limit_kb);
+        self.show_dry_run(ui);
+        ui.separator();
+    }
+
+    // Report of the last dry run
+    fn show_dry_run(&mut self, ui: &mut Ui) {
+        let Some(report) = &self.dry_run else {
+            return;
+        };
+        ui.add_space(5.);
+        ui.label(format!(
+            "{} files , {}",
+            report.files,
+            format_size(report.total, DECIMAL)
+        ));
+        if !report.deduplicated.is_empty() {
+            ui.label(format!(
+                "{} file(s) , {} already in the store",
+                report.deduplicated.len(),
+                format_size(report.deduplicated_size(), DECIMAL)
+            ));
+        }
+        if !report.rejected.is_empty() {
+            ui.colored_label(
+                Color32::LIGHT_RED,
+                format!("{} name(s) will be rejected", report.rejected.len()),
+            );
+        }
+        egui::CollapsingHeader::new("Details")
+            .id_salt("dry_run_details")
+            .show(ui, |ui| {
+                egui::ScrollArea::vertical()
+                    .id_salt("dry_run_scroll")
+                    .max_height(200.)
+                    .show(ui, |ui| {
+                        egui::Grid::new("dry_run_grid")
+                            .num_columns(2)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for (name, reason) in report.rejected.iter() {
+                                    ui.colored_label(Color32::LIGHT_RED, name);
+                                    ui.label(reason);
+                                    ui.end_row();
+                                }
+                                for (name, size) in report.largest.iter() {
+                                    ui.label(name);
+                                    ui.label(format_size(*size, DECIMAL));
+                                    ui.end_row();
+                                }
+                                for (name, size) in report.deduplicated.iter() {
+                                    ui.label(name);
+                                    ui.label(format!("{} , in store", format_size(*size, DECIMAL)));
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            });
+    }
+
+    // Drag and drop , files are staged for sending , tickets go to the fetch box
+    fn handle_drops(&mut self, ctx: &egui::Context) {
+        let dropped = ctx.input(|i| i.raw.dropped_files.clone());
+        for file in dropped {
+            let Some(path) = file.path else {
+                continue;
+            };
+            let is_ticket_file = path.extension().is_some_and(|ext| ext == TICKET_EXTENSION);
+            if is_ticket_file {
+                match TicketFile::load(&path) {
+                    Ok(file) => {
+                        self.ticket_note =
+                            (!file.description.is_empty()).then_some(file.description);
+                        self.fetch_ticket(file.ticket);
+                    }
+                    Err(err) => warn!("failed to read ticket {err}"),
+                }
+            } else if self.mode == AppMode::Idle && !self.picked_paths.contains(&path) {
+                self.picked_paths.push(path);
+                self.dry_run = None;
+            }
+        }
+        // winit only drops files, ticket text is taken from a paste anywhere
+        let focused = ctx.memory(|mem| mem.focused().is_some());
+        if !focused {
+            let pasted = ctx.input(|i| {
+                i.events.iter().find_map(|event| match event {
+                    egui::Event::Paste(text) if is_ticket(text) => Some(text.clone()),
+                    _ => None,
+                })
+            });
+            if let Some(ticket) = pasted {
+                self.ticket_note = None;
+                self.fetch_ticket(ticket);
+            }
+        }
+        // Show where things can go
+        if ctx.input(|i| !i.raw.hovered_files.is_empty()) {
+            let painter = ctx.layer_painter(egui::LayerId::new(
+                egui::Order::Foreground,
+                egui::Id::new("drop_zone"),
+            ));
+            let rect = ctx.screen_rect();
+            painter.rect_filled(rect, 0.0, Color32::from_black_alpha(192));
+            painter.rect_stroke(
+                rect.shrink(8.),
+                8.0,
+                egui::Stroke::new(2., Color32::LIGHT_BLUE),
+                egui::StrokeKind::Inside,
+            );
+            painter.text(
+                rect.center(),
+                egui::Align2::CENTER_CENTER,
+                "Drop files to send\nor a .sendme ticket to fetch",
+                egui::TextStyle::Heading.resolve(&ctx.style()),
+                Color32::WHITE,
+            );
+        }
+    }
+
+    // The ticket as fetched , with the last known addresses of a contact
+    // when it only has the id
+    fn ticket_to_fetch(&self) -> String {
+        self.contacts
+            .with_addrs(&self.receiver_ticket)
+            .unwrap_or_else(|| self.receiver_ticket.clone())
+    }
+
+    // Who the ticket in the fetch box is from
+    fn ticket_sender(&mut self, ui: &mut Ui) {
+        let Some(node_id) = ticket_node(&self.receiver_ticket) else {
+            return;
+        };
+        match self.contacts.name(node_id) {
+            Some(name) => {
+                ui.small(format!("From {}", name));
+            }
+            None => {
+                ui.horizontal(|ui| {
+                    ui.colored_label(
+                        Color32::LIGHT_YELLOW,
+                        format!("Unknown sender {}", node_id.fmt_short()),
+                    )
+                    .on_hover_text(
+                        "Not in the contacts , make sure the ticket is from who you think",
+                    );
+                    if ui.small_button("Add Contact").clicked() {
+                        self.contact_id_input = node_id.to_string();
+                        self.mode = AppMode::Contacts;
+                    }
+                });
+            }
+        }
+    }
+
+    // Put a ticket in the fetch box and look at what is in it
+    fn fetch_ticket(&mut self, ticket: String) {
+        if self.mode != AppMode::Idle {
+            return;
+        }
+        self.receiver_ticket = ticket;
+        self.preview = None;
+        self.cmd(Command::Preview(self.ticket_to_fetch()));
+    }
+
+    // Copy , QR code and save for the current ticket
+    fn ticket_tools(&mut self, ui: &mut Ui, ticket: &str) {
+        ui.horizontal(|ui| {
+            if ui.button("Copy").clicked() {
+                ui.ctx().copy_text(ticket.to_string());
+            }
+            ui.toggle_value(&mut self.show_qr, "QR Code");
+            if ui.button("Save Ticket As…").clicked()
+                && let Some(path) = rfd::FileDialog::new()
+                    .add_filter("sendme ticket", &[TICKET_EXTENSION])
+                    .set_file_name(format!("share.{}", TICKET_EXTENSION))
+                    .save_file()
+            {
+                let file = TicketFile {
+                    ticket: ticket.to_string(),
+                    description: format!(
+                        "{}\nshared {}",
+                        self.share_name.clone().unwrap_or_default(),
+                        chrono::Local::now().format("%Y-%m-%d %H:%M")
+                    ),
+                };
+                if let Err(err) = file.save(&path) {
+                    warn!("failed to save ticket {err}");
+                }
+            }
+        });
+        if self.show_qr {
+            // Only rebuild the texture when the ticket changes
+            if self.qr_texture.as_ref().map(|(text, _)| text.as_str()) != Some(ticket) {
+                self.qr_texture = qr_image(ticket).map(|image| {
+                    let texture =
+                        ui.ctx()
+                            .load_texture("ticket_qr", image, TextureOptions::NEAREST);
+                    (ticket.to_string(), texture)
+                });
+            }
+            match &self.qr_texture {
+                Some((_, texture)) => {
+                    ui.add_space(5.);
+                    ui.add(egui::Image::new(texture).fit_to_exact_size(egui::vec2(240., 240.)));
+                }
+                None => {
+                    ui.label("Ticket is too long for a QR code");
+                }
+            }
+        }
+    }
+
+    // Versions published by a watched share
+    fn version_history(&mut self, ui: &mut Ui) {
+        if self.versions.is_empty() {
+            return;
+        }
+        egui::CollapsingHeader::new(format!("Versions ({})", self.versions.len()))
+            .id_salt("version_history")
+            .default_open(true)
+            .show(ui, |ui| {
+                egui::Grid::new("version_grid")
+                    .num_columns(5)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for version in self.versions.iter().rev() {
+                            ui.label(format!("v{}", version.version));
+                            ui.label(version.created.format("%H:%M:%S").to_string());
+                            ui.label(format!("{} files", version.files));
+                            ui.label(format_size(version.size, DECIMAL));
+                            if ui
+                                .small_button("Copy ticket")
+                                .on_hover_text(&version.ticket)
+                                .clicked()
+                            {
+                                ui.ctx().copy_text(version.ticket.clone());
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+        ui.add_space(5.);
+    }
+
+    // List of outgoing tags that can be served again
+    fn previous_shares(&mut self, ui: &mut Ui) {
+        ui.add_space(5.);
+        let mut reshare = None;
+        egui::CollapsingHeader::new(format!("Previous shares ({})", self.shares.len()))
+            .id_salt("previous_shares")
+            .show(ui, |ui| {
+                if ui.small_button("Refresh").clicked() {
+                    self.cmd(Command::ListShares);
+                }
+                egui::ScrollArea::vertical()
+                    .id_salt("previous_shares_scroll")
+                    .max_height(150.)
+                    .show(ui, |ui| {
+                        egui::Grid::new("previous_shares_grid")
+                            .num_columns(4)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for entry in self.shares.iter() {
+                                    ui.label(&entry.name).on_hover_text(format!(
+                                        "{}\n{} files",
+                                        entry.tag, entry.files
+                                    ));
+                                    ui.label(format_size(entry.size, DECIMAL));
+                                    match entry.created {
+                                        Some(date) => {
+                                            ui.label(date.format("%Y-%m-%d %H:%M").to_string())
+                                        }
+                                        None => ui.label(""),
+                                    };
+                                    if ui.button("Share").clicked() {
+                                        reshare = Some(entry.clone());
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                    });
+            });
+        if let Some(entry) = reshare {
+            self.share_name = Some(entry.name);
+            self.send_ticket = None;
+            self.versions.clear();
+            self.cmd(Command::Reshare((entry.hash, self.ticket_type)));
+            self.mode = AppMode::SendProgress;
+        }
+    }
+
+    fn save_contacts(&self) {
+        if let Err(err) = self.contacts.save(&contacts_path()) {
+            warn!("failed to save contacts {err}");
+        }
+        self.apply_senders();
+    }
+
+    // Who may push , straight to the inbox so it holds during a fetch
+    fn apply_senders(&self) {
+        self.config
+            .apply_senders(&self.worker.inbox, &self.contacts);
+    }
+
+    // Write the config back to disk
+    fn save_config(&self) {
+        if let Err(err) = confy::store("sendme-egui", None, &self.config) {
+            warn!("failed to save config {err}");
+        }
     }
 
     // Reset the application
     fn reset(&mut self) {
         self.mode = AppMode::Idle;
         self.receiver_ticket = "".to_string();
+        self.ticket_note = None;
+        self.preview = None;
+        self.dry_run = None;
+        self.picked_paths.clear();
         self.messages = Vec::new();
         self.progress.clear();
+        self.connections.clear();
     }
 
     // Show the list of progress bars
@@ -352,6 +2240,79 @@ impl AppState {
         self.progress.show(ui);
     }
 
+    // Path , rtt and history of every connection seen
+    fn show_connections(&mut self, ui: &mut Ui) {
+        if self.connections.is_empty() {
+            return;
+        }
+        ui.add_space(4.);
+        let open = self.connections.iter().filter(|diag| !diag.closed).count();
+        egui::CollapsingHeader::new(format!("Connections , {} open", open))
+            .id_salt("connections")
+            .show(ui, |ui| {
+                egui::Grid::new("connection_grid")
+                    .num_columns(6)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for diag in &self.connections {
+                            ui.label(diag.role);
+                            ui.small(self.contacts.label(diag.node_id))
+                                .on_hover_text(diag.node_id.to_string());
+                            let color = match diag.kind {
+                                _ if diag.closed => Color32::GRAY,
+                                PathKind::Direct => Color32::LIGHT_GREEN,
+                                PathKind::Mixed => Color32::LIGHT_YELLOW,
+                                PathKind::Relay => Color32::ORANGE,
+                                PathKind::None => Color32::LIGHT_RED,
+                            };
+                            ui.colored_label(color, diag.kind.to_string());
+                            let via = match (&diag.remote, &diag.relay) {
+                                (Some(remote), _) => remote.to_string(),
+                                (None, Some(relay)) => relay.to_string(),
+                                (None, None) => "-".to_string(),
+                            };
+                            ui.small(via).on_hover_text(
+                                diag.addrs
+                                    .iter()
+                                    .map(|addr| addr.to_string())
+                                    .collect::<Vec<_>>()
+                                    .join("\n"),
+                            );
+                            ui.small(match diag.rtt {
+                                Some(rtt) => format!("{} ms", rtt.as_millis()),
+                                None => "-".to_string(),
+                            });
+                            ui.small(
+                                diag.history
+                                    .iter()
+                                    .map(|(at, kind)| {
+                                        format!("{} at {:.1} s", kind, at.as_secs_f32())
+                                    })
+                                    .collect::<Vec<_>>()
+                                    .join(" , "),
+                            );
+                            ui.end_row();
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    if ui.small_button("Copy Diagnostics").clicked() {
+                        let report = self
+                            .connections
+                            .iter()
+                            .map(|diag| diag.to_string())
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        let report =
+                            format!("sendme-egui {}\n{}", env!("CARGO_PKG_VERSION"), report);
+                        ui.ctx().copy_text(report);
+                    }
+                    if ui.small_button("Clear").clicked() {
+                        self.connections.retain(|diag| !diag.closed);
+                    }
+                });
+            });
+    }
+
     // Show the list of messages
     fn show_messages(&mut self, ui: &mut Ui) {
         ui.add_space(4.);
@@ -384,6 +2345,37 @@ impl AppState {
     }
 }
 
+// Render a ticket as a QR code, one pixel per module with a quiet zone
+fn qr_image(text: &str) -> Option<ColorImage> {
+    const QUIET: usize = 4;
+    let code = qrcode::QrCode::new(text.as_bytes()).ok()?;
+    let width = code.width();
+    let size = width + QUIET * 2;
+    let mut image = ColorImage::filled([size, size], Color32::WHITE);
+    for (i, color) in code.to_colors().into_iter().enumerate() {
+        if color == qrcode::Color::Dark {
+            let (x, y) = (i % width + QUIET, i / width + QUIET);
+            image.pixels[y * size + x] = Color32::BLACK;
+        }
+    }
+    Some(image)
+}
+
+// Pick what address information goes into a ticket
+fn ticket_combo(ui: &mut Ui, id: &str, value: &mut AddrInfoOptions, prefix: bool) {
+    let text = match prefix {
+        true => format!("Ticket: {}", value),
+        false => value.to_string(),
+    };
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(text)
+        .show_ui(ui, |ui| {
+            for option in AddrInfoOptions::ALL {
+                ui.selectable_value(value, option, option.to_string());
+            }
+        });
+}
+
 fn format_seconds_as_hms(total_seconds: u64) -> String {
     let hours = total_seconds / 3600;
     let minutes = (total_seconds % 3600) / 60;
@@ -10,6 +10,14 @@ use eframe::egui::{self};
 use egui::{Color32, Ui};
 use tokio::sync::Mutex;
 
+use crate::transport::{
+    AddrInfoOptions, ConnectionDiag, DryRun, FetchOptions, GcOptions, GcReport, IncomingOffer,
+    NearbyPeer, NetOptions, Offer, Preview, ReplicaStatus, SeedStats, SendOptions, ShareVersion,
+    TagEntry, TreeItem, VerifyReport,
+};
+use iroh::{NodeAddr, NodeId};
+use iroh_blobs::Hash;
+
 // Update Callback
 type UpdateCallback = Box<dyn Fn() + Send + 'static>;
 
@@ -20,6 +28,24 @@ pub enum Event {
     ProgressFinished(String),
     ProgressComplete(String),
     SendTicket(String),
+    Shares(Vec<TagEntry>),
+    StoreTags(Vec<TagEntry>),
+    Tree((Hash, Vec<TreeItem>)),
+    GcReport(GcReport),
+    StoreMoved(PathBuf),
+    Verify(VerifyReport),
+    BlobFixed(Hash),
+    Seeding(Vec<SeedStats>),
+    NodeId(NodeId),
+    Replica(ReplicaStatus),
+    Nearby(Vec<NearbyPeer>),
+    Connection(ConnectionDiag),
+    Offer(IncomingOffer),
+    ShareVersion(ShareVersion),
+    Preview(Preview),
+    DryRun(DryRun),
+    // The worker has stopped and takes no more commands
+    Fatal(String),
     Tick(u64),
     StopTick,
     Finished,
@@ -28,9 +54,39 @@ pub enum Event {
 // Outgoing Commands
 pub enum Command {
     Setup { callback: UpdateCallback },
-    Send(PathBuf),
-    Fetch((String, PathBuf)),
-    CancelTest
+    Send((Vec<PathBuf>, SendOptions)),
+    DryRun(Vec<PathBuf>),
+    WatchShare((PathBuf, SendOptions)),
+    Reshare((Hash, AddrInfoOptions)),
+    StopShare,
+    ListShares,
+    ListStore,
+    DeleteTag(String),
+    RenameTag((String, String)),
+    TagNote((String, String)),
+    InspectTag(Hash),
+    ExportTag((Hash, PathBuf)),
+    ExportBundle((Hash, PathBuf)),
+    PinTag((String, Hash)),
+    UnpinTag(String),
+    SeedTag((String, Hash)),
+    UnseedTag(String),
+    ListSeeds,
+    ReplicaSettings((Vec<NodeId>, bool)),
+    Replicate((String, Hash)),
+    InboxSettings(bool),
+    Push((NodeAddr, Offer)),
+    GcSettings(GcOptions),
+    NetSettings(NetOptions),
+    CollectGarbage,
+    MoveStore((PathBuf, bool)),
+    VerifyStore,
+    RepairBlob(Hash),
+    DropBlob(Hash),
+    Preview(String),
+    Fetch((String, PathBuf, FetchOptions)),
+    ImportBundle((PathBuf, PathBuf, FetchOptions)),
+    CancelTest,
 }
 
 // Message types
@@ -143,10 +199,102 @@ impl MessageOut {
         Ok(())
     }
 
-    pub async fn send_ticket(&self, ticket: String) -> Result<()> { 
+    pub async fn send_ticket(&self, ticket: String) -> Result<()> {
         self.emit(Event::SendTicket(ticket)).await?;
         Ok(())
     }
+
+    pub async fn share_version(&self, version: ShareVersion) -> Result<()> {
+        self.emit(Event::ShareVersion(version)).await?;
+        Ok(())
+    }
+
+    pub async fn preview(&self, preview: Preview) -> Result<()> {
+        self.emit(Event::Preview(preview)).await?;
+        Ok(())
+    }
+
+    pub async fn dry_run(&self, report: DryRun) -> Result<()> {
+        self.emit(Event::DryRun(report)).await?;
+        Ok(())
+    }
+
+    pub async fn shares(&self, shares: Vec<TagEntry>) -> Result<()> {
+        self.emit(Event::Shares(shares)).await?;
+        Ok(())
+    }
+
+    pub async fn store_tags(&self, tags: Vec<TagEntry>) -> Result<()> {
+        self.emit(Event::StoreTags(tags)).await?;
+        Ok(())
+    }
+
+    pub async fn gc_report(&self, report: GcReport) -> Result<()> {
+        self.emit(Event::GcReport(report)).await?;
+        Ok(())
+    }
+
+    pub async fn store_moved(&self, path: PathBuf) -> Result<()> {
+        self.emit(Event::StoreMoved(path)).await?;
+        Ok(())
+    }
+
+    pub async fn fatal(&self, message: &str) -> Result<()> {
+        self.emit(Event::Fatal(message.to_string())).await?;
+        Ok(())
+    }
+
+    pub async fn verify(&self, report: VerifyReport) -> Result<()> {
+        self.emit(Event::Verify(report)).await?;
+        Ok(())
+    }
+
+    pub async fn blob_fixed(&self, hash: Hash) -> Result<()> {
+        self.emit(Event::BlobFixed(hash)).await?;
+        Ok(())
+    }
+
+    pub async fn seeding(&self, stats: Vec<SeedStats>) -> Result<()> {
+        self.emit(Event::Seeding(stats)).await?;
+        Ok(())
+    }
+
+    pub async fn node_id(&self, node_id: NodeId) -> Result<()> {
+        self.emit(Event::NodeId(node_id)).await?;
+        Ok(())
+    }
+
+    pub async fn replica(&self, status: ReplicaStatus) -> Result<()> {
+        self.emit(Event::Replica(status)).await?;
+        Ok(())
+    }
+
+    pub async fn offer(&self, offer: IncomingOffer) -> Result<()> {
+        self.emit(Event::Offer(offer)).await?;
+        Ok(())
+    }
+
+    pub async fn nearby(&self, peers: Vec<NearbyPeer>) -> Result<()> {
+        self.emit(Event::Nearby(peers)).await?;
+        Ok(())
+    }
+
+    pub async fn connection(&self, diag: ConnectionDiag) -> Result<()> {
+        self.emit(Event::Connection(diag)).await?;
+        Ok(())
+    }
+
+    pub async fn tree(&self, hash: Hash, items: Vec<TreeItem>) -> Result<()> {
+        self.emit(Event::Tree((hash, items))).await?;
+        Ok(())
+    }
+}
+
+// Plain text for the log when headless
+impl std::fmt::Display for MessageDisplay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
 }
 
 // Message formatting
@@ -173,7 +321,6 @@ impl MessageDisplay {
     }
 }
 
-
 // --------
 // Progress Bars
 // --------
@@ -229,7 +376,7 @@ impl ProgressList {
             self.bars.insert(
                 name.to_owned(),
                 ProgressBar {
-                    name: name,
+                    name,
                     current,
                     total,
                     complete: false,
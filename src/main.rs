@@ -18,5 +18,12 @@ fn main() -> eframe::Result {
         .with_resizable(true)
         .with_inner_size([320., 400.])
         .with_drag_and_drop(true); // So cool !!
-    App::run(options)
+    // Kiosk mode , the blob store lives in memory
+    let ephemeral = std::env::args().any(|arg| arg == "--ephemeral");
+    // No window , just serve the seeded tags
+    if std::env::args().any(|arg| arg == "--headless") {
+        App::headless();
+        return Ok(());
+    }
+    App::run(options, ephemeral)
 }
@@ -0,0 +1,301 @@
+// Offline bundles
+// A collection as one file for machines without a network between them.
+//
+// The file is a header and then every blob of the collection in the
+// verified bao encoding , cut into ranges so no blob has to fit in
+// memory. Importing checks each range against its hash on the way in.
+//
+// header : magic , version , root hash
+// record : hash , first chunk , end chunk , length , bao bytes
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use iroh_blobs::api::Store;
+use iroh_blobs::api::blobs::BlobStatus;
+use iroh_blobs::hashseq::HashSeq;
+use iroh_blobs::protocol::{ChunkRanges, ChunkRangesExt};
+use iroh_blobs::{Hash, HashAndFormat};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+
+use super::fetch::{FetchOptions, export_fetched, tag_incoming};
+use super::gc::Busy;
+use crate::comms::MessageOut;
+
+/// File extension of bundles.
+pub const BUNDLE_EXTENSION: &str = "sendme-bundle";
+
+const MAGIC: &[u8; 13] = b"sendme-bundle";
+const VERSION: u8 = 1;
+// Chunks of 1 KiB in one record
+const RANGE_CHUNKS: u64 = 16 * 1024;
+// Stands for "to the end of the blob"
+const OPEN_END: u64 = u64::MAX;
+// Bao bytes of one record can not be more than this
+const MAX_RECORD: u64 = 2 * RANGE_CHUNKS * 1024 + 64 * 1024;
+
+/// Write a complete collection to a bundle file.
+pub async fn export_bundle(
+    store: &Store,
+    hash: Hash,
+    path: &Path,
+    mess: &MessageOut,
+) -> Result<u64> {
+    let local = store.remote().local(HashAndFormat::hash_seq(hash)).await?;
+    anyhow::ensure!(local.is_complete(), "the collection is not complete");
+    let res = write_bundle(store, hash, path, mess).await;
+    if res.is_err() {
+        let _ = tokio::fs::remove_file(path).await;
+    }
+    res
+}
+
+async fn write_bundle(store: &Store, root: Hash, path: &Path, mess: &MessageOut) -> Result<u64> {
+    // The hash sequence first , then its blobs once each
+    let children = HashSeq::try_from(store.blobs().get_bytes(root).await?)?;
+    let mut seen = HashSet::new();
+    let mut blobs = Vec::new();
+    for hash in std::iter::once(root).chain(children.iter()) {
+        if !seen.insert(hash) {
+            continue;
+        }
+        let BlobStatus::Complete { size } = store.blobs().status(hash).await? else {
+            anyhow::bail!("blob {} is missing", hash.fmt_short());
+        };
+        blobs.push((hash, size));
+    }
+    let total = blobs.iter().map(|(_, size)| *size).sum::<u64>();
+
+    let mut file = BufWriter::new(tokio::fs::File::create(path).await?);
+    file.write_all(MAGIC).await?;
+    file.write_u8(VERSION).await?;
+    file.write_all(root.as_bytes()).await?;
+    let mut base = 0;
+    let mut written = 0;
+    for (hash, size) in blobs {
+        let chunks = size.div_ceil(1024);
+        let mut start = 0;
+        loop {
+            let end = start + RANGE_CHUNKS;
+            // The last range is open so it carries the size proof
+            let (ranges, end) = if end >= chunks {
+                (ChunkRanges::chunks(start..), OPEN_END)
+            } else {
+                (ChunkRanges::chunks(start..end), end)
+            };
+            let data = store.blobs().export_bao(hash, ranges).bao_to_vec().await?;
+            file.write_all(hash.as_bytes()).await?;
+            file.write_u64_le(start).await?;
+            file.write_u64_le(end).await?;
+            file.write_u64_le(data.len() as u64).await?;
+            file.write_all(&data).await?;
+            written += data.len() as u64;
+            if end == OPEN_END {
+                base += size;
+                mess.progress("Bundle", base as usize, total as usize)
+                    .await?;
+                break;
+            }
+            mess.progress("Bundle", (base + end * 1024) as usize, total as usize)
+                .await?;
+            start = end;
+        }
+    }
+    file.flush().await?;
+    mess.complete("Bundle").await?;
+    mess.progress_finish("Bundle").await?;
+    Ok(written)
+}
+
+/// Load a bundle into the store and export it like a finished fetch.
+pub async fn import_bundle(
+    path: PathBuf,
+    target: PathBuf,
+    options: FetchOptions,
+    mess: MessageOut,
+    db: Store,
+) -> Result<()> {
+    let file = tokio::fs::File::open(&path).await?;
+    let length = file.metadata().await?.len();
+    let mut reader = BufReader::new(file);
+    let root = read_header(&mut reader)
+        .await
+        .with_context(|| format!("{} is not a bundle", path.display()))?;
+
+    let local = db.remote().local(HashAndFormat::hash_seq(root)).await?;
+    let incoming = if local.is_complete() {
+        mess.correct("Collection is complete and local!").await?;
+        None
+    } else {
+        // Keep the gc off the partial import
+        let busy = match options.ephemeral {
+            true => None,
+            false => Some(Busy::protect(&db, "bundle", root).await?),
+        };
+        read_records(&db, root, &mut reader, length, &mess).await?;
+        let local = db.remote().local(HashAndFormat::hash_seq(root)).await?;
+        anyhow::ensure!(local.is_complete(), "the bundle is incomplete");
+        mess.correct("Bundle imported").await?;
+        let tag = match options.ephemeral {
+            true => None,
+            false => Some(tag_incoming(&db, root).await?),
+        };
+        if let Some(busy) = busy {
+            busy.release().await?;
+        }
+        tag
+    };
+    export_fetched(&db, root, incoming, target, options, &mess).await
+}
+
+async fn read_header(reader: &mut BufReader<tokio::fs::File>) -> Result<Hash> {
+    let mut magic = [0u8; MAGIC.len()];
+    reader.read_exact(&mut magic).await?;
+    anyhow::ensure!(&magic == MAGIC, "wrong magic");
+    let version = reader.read_u8().await?;
+    anyhow::ensure!(
+        version == VERSION,
+        "bundle version {} is not known",
+        version
+    );
+    let mut root = [0u8; 32];
+    reader.read_exact(&mut root).await?;
+    Ok(Hash::from_bytes(root))
+}
+
+// Import every record , each range is verified by the store
+async fn read_records(
+    store: &Store,
+    root: Hash,
+    reader: &mut BufReader<tokio::fs::File>,
+    length: u64,
+    mess: &MessageOut,
+) -> Result<()> {
+    // The blobs of the collection , known once the root is in
+    let mut allowed = None::<HashSet<Hash>>;
+    let mut read = 0;
+    let mut bytes = [0u8; 32];
+    loop {
+        // A clean end is only allowed between records
+        if reader.read(&mut bytes[..1]).await? == 0 {
+            break;
+        }
+        reader.read_exact(&mut bytes[1..]).await?;
+        let hash = Hash::from_bytes(bytes);
+        let start = reader.read_u64_le().await?;
+        let end = reader.read_u64_le().await?;
+        let len = reader.read_u64_le().await?;
+        anyhow::ensure!(
+            len <= MAX_RECORD,
+            "record of {} is too large",
+            hash.fmt_short()
+        );
+        if hash != root {
+            if allowed.is_none() {
+                let children = HashSeq::try_from(
+                    store
+                        .blobs()
+                        .get_bytes(root)
+                        .await
+                        .context("the bundle does not start with its collection")?,
+                )?;
+                allowed = Some(children.iter().collect());
+            }
+            anyhow::ensure!(
+                allowed
+                    .as_ref()
+                    .is_some_and(|allowed| allowed.contains(&hash)),
+                "blob {} is not part of the collection",
+                hash.fmt_short()
+            );
+        }
+        let ranges = if end == OPEN_END {
+            ChunkRanges::chunks(start..)
+        } else {
+            ChunkRanges::chunks(start..end)
+        };
+        let mut data = vec![0u8; len as usize];
+        reader.read_exact(&mut data).await?;
+        store
+            .blobs()
+            .import_bao_bytes(hash, ranges, data)
+            .await
+            .with_context(|| format!("blob {} is damaged", hash.fmt_short()))?;
+        read += len + 56;
+        mess.progress("Bundle", read as usize, length as usize)
+            .await?;
+    }
+    mess.complete("Bundle").await?;
+    mess.progress_finish("Bundle").await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_blobs::format::collection::Collection;
+
+    use super::*;
+    use crate::transport::memory_store;
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sendme-bundle-{}-{}", name, std::process::id()))
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn write_and_import_roundtrip() -> Result<()> {
+        // Big enough for more than one record
+        let big = (0..RANGE_CHUNKS * 1024 + 5000)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let source = memory_store();
+        let small_hash = source.add_bytes(b"hello".to_vec()).await?.hash;
+        let big_hash = source.add_bytes(big.clone()).await?.hash;
+        let collection = [("docs/a.txt", small_hash), ("docs/big.bin", big_hash)]
+            .into_iter()
+            .collect::<Collection>();
+        let root = *collection.store(&source).await?.hash();
+
+        let dir = scratch("roundtrip");
+        std::fs::create_dir_all(&dir)?;
+        let (event_tx, _event_rx) = async_channel::unbounded();
+        let mess = MessageOut::new(event_tx);
+        let path = dir.join(format!("docs.{}", BUNDLE_EXTENSION));
+        let res = async {
+            export_bundle(&source, root, &path, &mess).await?;
+            let target = memory_store();
+            let out = dir.join("out");
+            import_bundle(
+                path.clone(),
+                out.clone(),
+                FetchOptions::default(),
+                mess.clone(),
+                target.clone(),
+            )
+            .await?;
+            let local = target.remote().local(HashAndFormat::hash_seq(root)).await?;
+            anyhow::ensure!(local.is_complete(), "import is not complete");
+            anyhow::ensure!(std::fs::read(out.join("docs/a.txt"))? == b"hello");
+            anyhow::ensure!(std::fs::read(out.join("docs/big.bin"))? == big);
+
+            // A flipped byte in the data is caught
+            let mut bytes = std::fs::read(&path)?;
+            let at = bytes.len() - 100;
+            bytes[at] ^= 0xff;
+            std::fs::write(&path, bytes)?;
+            let res = import_bundle(
+                path.clone(),
+                dir.join("bad"),
+                FetchOptions::default(),
+                mess.clone(),
+                memory_store(),
+            )
+            .await;
+            anyhow::ensure!(res.is_err(), "damaged bundle imported");
+            Ok(())
+        }
+        .await;
+        std::fs::remove_dir_all(&dir)?;
+        res
+    }
+}
@@ -0,0 +1,276 @@
+// Address book
+// The people we send to often , a name for a node id and where the node
+// was last seen.
+//
+// Kept as json in the data dir. The gui owns the book and looks names up
+// wherever a node id is shown. The last known addresses help a ticket
+// that only carries the id when discovery can not find the node.
+
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Result;
+use iroh::{NodeAddr, NodeId};
+use iroh_blobs::ticket::BlobTicket;
+use serde_derive::{Deserialize, Serialize};
+
+use super::ticket::clean_ticket;
+
+// Addresses kept per contact , the newest first
+const MAX_ADDRS: usize = 4;
+
+/// A known node.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Contact {
+    pub name: String,
+    pub node_id: NodeId,
+    #[serde(default)]
+    pub addrs: Vec<SocketAddr>,
+}
+
+/// The address book , a list in the file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Contacts(Vec<Contact>);
+
+impl Contacts {
+    /// Read the book , a missing file is an empty book.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Merge another book in , names from the file win. Returns how many
+    /// contacts were new.
+    pub fn import(&mut self, path: &Path) -> Result<usize> {
+        let other = Self::load(path)?;
+        let mut added = 0;
+        for contact in other.0 {
+            match self.find_mut(contact.node_id) {
+                Some(known) => {
+                    known.name = contact.name;
+                    for addr in contact.addrs {
+                        push_addr(&mut known.addrs, addr);
+                    }
+                }
+                None => {
+                    self.0.push(contact);
+                    added += 1;
+                }
+            }
+        }
+        Ok(added)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Contact> {
+        self.0.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Contact> {
+        self.0.iter_mut()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Add a contact or rename a known one.
+    pub fn add(&mut self, name: &str, node_id: NodeId) {
+        match self.find_mut(node_id) {
+            Some(known) => known.name = name.to_string(),
+            None => self.0.push(Contact {
+                name: name.to_string(),
+                node_id,
+                addrs: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn remove(&mut self, node_id: NodeId) {
+        self.0.retain(|contact| contact.node_id != node_id);
+    }
+
+    pub fn name(&self, node_id: NodeId) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|contact| contact.node_id == node_id)
+            .map(|contact| contact.name.as_str())
+    }
+
+    /// The name of a node , or its short id for strangers.
+    pub fn label(&self, node_id: NodeId) -> String {
+        match self.name(node_id) {
+            Some(name) => name.to_string(),
+            None => node_id.fmt_short(),
+        }
+    }
+
+    /// Where to reach a node , its id and the last known addresses.
+    pub fn node_addr(&self, node_id: NodeId) -> NodeAddr {
+        let addrs = self
+            .0
+            .iter()
+            .find(|contact| contact.node_id == node_id)
+            .map(|contact| contact.addrs.clone())
+            .unwrap_or_default();
+        NodeAddr::from_parts(node_id, None, addrs)
+    }
+
+    /// Note where a contact was reached , true when the book changed.
+    pub fn seen(&mut self, node_id: NodeId, addr: SocketAddr) -> bool {
+        let Some(known) = self.find_mut(node_id) else {
+            return false;
+        };
+        if known.addrs.first() == Some(&addr) {
+            return false;
+        }
+        push_addr(&mut known.addrs, addr);
+        true
+    }
+
+    /// The ticket with the last known addresses of its node added , when
+    /// it has none of its own and the node is a contact.
+    pub fn with_addrs(&self, ticket: &str) -> Option<String> {
+        let ticket = BlobTicket::from_str(&clean_ticket(ticket)).ok()?;
+        let mut addr = ticket.node_addr().clone();
+        if !addr.direct_addresses.is_empty() {
+            return None;
+        }
+        let known = self
+            .0
+            .iter()
+            .find(|contact| contact.node_id == addr.node_id)?;
+        if known.addrs.is_empty() {
+            return None;
+        }
+        addr.direct_addresses = known.addrs.iter().copied().collect::<BTreeSet<_>>();
+        Some(BlobTicket::new(addr, ticket.hash(), ticket.format()).to_string())
+    }
+
+    fn find_mut(&mut self, node_id: NodeId) -> Option<&mut Contact> {
+        self.0.iter_mut().find(|contact| contact.node_id == node_id)
+    }
+}
+
+fn push_addr(addrs: &mut Vec<SocketAddr>, addr: SocketAddr) {
+    addrs.retain(|known| *known != addr);
+    addrs.insert(0, addr);
+    addrs.truncate(MAX_ADDRS);
+}
+
+/// The node a ticket points at.
+pub fn ticket_node(text: &str) -> Option<NodeId> {
+    BlobTicket::from_str(&clean_ticket(text))
+        .ok()
+        .map(|ticket| ticket.node_addr().node_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+    use iroh_blobs::{BlobFormat, Hash};
+
+    use super::*;
+
+    fn node() -> NodeId {
+        SecretKey::generate(rand::rngs::OsRng).public()
+    }
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([192, 168, 1, 10], port))
+    }
+
+    fn scratch(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "sendme-contacts-{}-{}.json",
+            name,
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn seen_keeps_the_newest_addresses() {
+        let mut contacts = Contacts::default();
+        let friend = node();
+        assert!(!contacts.seen(friend, addr(1)));
+        contacts.add("friend", friend);
+        assert!(contacts.seen(friend, addr(1)));
+        assert!(!contacts.seen(friend, addr(1)));
+        for port in 2..=6 {
+            assert!(contacts.seen(friend, addr(port)));
+        }
+        let known = contacts.iter().next().unwrap();
+        assert_eq!(known.addrs, vec![addr(6), addr(5), addr(4), addr(3)]);
+        assert_eq!(contacts.label(friend), "friend");
+        let stranger = node();
+        assert_eq!(contacts.label(stranger), stranger.fmt_short());
+    }
+
+    #[test]
+    fn with_addrs_fills_id_only_tickets() {
+        let mut contacts = Contacts::default();
+        let friend = node();
+        let hash = Hash::new(b"collection");
+        let id_only = BlobTicket::new(friend.into(), hash, BlobFormat::HashSeq).to_string();
+        // Not a contact yet , and then a contact without addresses
+        assert_eq!(contacts.with_addrs(&id_only), None);
+        contacts.add("friend", friend);
+        assert_eq!(contacts.with_addrs(&id_only), None);
+
+        contacts.seen(friend, addr(1));
+        let filled = contacts
+            .with_addrs(&format!("sendme receive {id_only}"))
+            .unwrap();
+        let ticket = BlobTicket::from_str(&filled).unwrap();
+        assert_eq!(ticket.node_addr().node_id, friend);
+        assert_eq!(ticket.hash(), hash);
+        assert!(ticket.node_addr().direct_addresses.contains(&addr(1)));
+        assert_eq!(ticket_node(&filled), Some(friend));
+
+        // A ticket with its own addresses is left alone
+        let direct = NodeAddr::from_parts(friend, None, [addr(2)]);
+        let direct = BlobTicket::new(direct, hash, BlobFormat::HashSeq).to_string();
+        assert_eq!(contacts.with_addrs(&direct), None);
+    }
+
+    #[test]
+    fn import_merges_by_node() -> Result<()> {
+        let (known, new) = (node(), node());
+        let mut contacts = Contacts::default();
+        contacts.add("old name", known);
+        contacts.seen(known, addr(1));
+
+        let mut other = Contacts::default();
+        other.add("new name", known);
+        other.seen(known, addr(2));
+        other.add("someone", new);
+        let path = scratch("import");
+        other.save(&path)?;
+        let added = contacts.import(&path);
+        std::fs::remove_file(&path)?;
+
+        assert_eq!(added?, 1);
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(contacts.name(known), Some("new name"));
+        assert_eq!(contacts.name(new), Some("someone"));
+        assert_eq!(contacts.node_addr(known).direct_addresses.len(), 2);
+        assert!(Contacts::load(&scratch("missing"))?.is_empty());
+        Ok(())
+    }
+}
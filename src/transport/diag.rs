@@ -0,0 +1,187 @@
+// Connection diagnostics
+// How each connection of a send or fetch reaches the other side.
+//
+// The endpoint knows per remote node whether packets go direct , over a
+// relay or both while it holepunches , which addresses it tried and the
+// latency. A watch reads that once a second while the connection is up
+// and sends it to the gui , changes of the path are kept as history.
+
+use std::fmt::{self, Display};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use iroh::endpoint::ConnectionType;
+use iroh::{Endpoint, NodeId, RelayUrl};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::comms::MessageOut;
+
+// How often the endpoint is asked
+const POLL: Duration = Duration::from_secs(1);
+
+/// The way packets reach the remote node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathKind {
+    Direct,
+    Relay,
+    /// Relayed while a direct path is tried
+    Mixed,
+    None,
+}
+
+impl Display for PathKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let val = match self {
+            PathKind::Direct => "direct",
+            PathKind::Relay => "relay",
+            PathKind::Mixed => "mixed",
+            PathKind::None => "none",
+        };
+        f.write_str(val)
+    }
+}
+
+/// One connection as the endpoint sees it.
+#[derive(Debug, Clone)]
+pub struct ConnectionDiag {
+    pub node_id: NodeId,
+    /// Send , seed or fetch
+    pub role: &'static str,
+    pub started: SystemTime,
+    pub kind: PathKind,
+    /// The direct address in use
+    pub remote: Option<SocketAddr>,
+    /// Every address known for the node
+    pub addrs: Vec<SocketAddr>,
+    pub relay: Option<RelayUrl>,
+    pub rtt: Option<Duration>,
+    /// Path changes , time since the start and the new path
+    pub history: Vec<(Duration, PathKind)>,
+    pub closed: bool,
+}
+
+impl ConnectionDiag {
+    fn new(node_id: NodeId, role: &'static str) -> Self {
+        Self {
+            node_id,
+            role,
+            started: SystemTime::now(),
+            kind: PathKind::None,
+            remote: None,
+            addrs: Vec::new(),
+            relay: None,
+            rtt: None,
+            history: Vec::new(),
+            closed: false,
+        }
+    }
+
+    // Take in what the endpoint knows now
+    fn update(&mut self, endpoint: &Endpoint) {
+        let Some(info) = endpoint.remote_info(self.node_id) else {
+            return;
+        };
+        let (kind, remote, relay) = match info.conn_type {
+            ConnectionType::Direct(addr) => (PathKind::Direct, Some(addr), None),
+            ConnectionType::Relay(url) => (PathKind::Relay, None, Some(url)),
+            ConnectionType::Mixed(addr, url) => (PathKind::Mixed, Some(addr), Some(url)),
+            ConnectionType::None => (PathKind::None, None, None),
+        };
+        if kind != self.kind || self.history.is_empty() {
+            let elapsed = self.started.elapsed().unwrap_or_default();
+            info!("{} {} path {}", self.role, self.node_id.fmt_short(), kind);
+            self.history.push((elapsed, kind));
+        }
+        self.kind = kind;
+        self.remote = remote;
+        // Keep the last relay , it is still the fallback when direct
+        self.relay = relay.or(info.relay_url.map(|relay| relay.relay_url));
+        self.addrs = info.addrs.iter().map(|addr| addr.addr).collect();
+        self.rtt = info.latency;
+    }
+}
+
+// Text for bug reports
+impl Display for ConnectionDiag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", self.role, self.node_id)?;
+        writeln!(
+            f,
+            "  path {}{}",
+            self.kind,
+            if self.closed { " , closed" } else { "" }
+        )?;
+        if let Some(remote) = self.remote {
+            writeln!(f, "  remote {}", remote)?;
+        }
+        if let Some(relay) = &self.relay {
+            writeln!(f, "  relay {}", relay)?;
+        }
+        if let Some(rtt) = self.rtt {
+            writeln!(f, "  rtt {} ms", rtt.as_millis())?;
+        }
+        for addr in &self.addrs {
+            writeln!(f, "  addr {}", addr)?;
+        }
+        for (at, kind) in &self.history {
+            writeln!(f, "  {:>6.1} s {}", at.as_secs_f32(), kind)?;
+        }
+        Ok(())
+    }
+}
+
+/// Follows one connection until finished or dropped.
+pub(super) struct ConnectionWatch {
+    diag: Arc<Mutex<ConnectionDiag>>,
+    task: JoinHandle<()>,
+    mess: MessageOut,
+}
+
+impl ConnectionWatch {
+    pub(super) fn start(
+        endpoint: &Endpoint,
+        node_id: NodeId,
+        role: &'static str,
+        mess: MessageOut,
+    ) -> Self {
+        let diag = Arc::new(Mutex::new(ConnectionDiag::new(node_id, role)));
+        let task = tokio::spawn(poll(endpoint.clone(), diag.clone(), mess.clone()));
+        Self { diag, task, mess }
+    }
+
+    /// Stop watching and send the last state , marked closed.
+    pub(super) async fn finish(self) {
+        self.task.abort();
+        let diag = {
+            let mut diag = self.diag.lock().expect("diag lock");
+            diag.closed = true;
+            diag.clone()
+        };
+        if let Err(err) = self.mess.connection(diag).await {
+            warn!("connection info not sent {err}");
+        }
+    }
+}
+
+impl Drop for ConnectionWatch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn poll(endpoint: Endpoint, diag: Arc<Mutex<ConnectionDiag>>, mess: MessageOut) {
+    let mut ticks = tokio::time::interval(POLL);
+    loop {
+        ticks.tick().await;
+        let current = {
+            let mut diag = diag.lock().expect("diag lock");
+            diag.update(&endpoint);
+            diag.clone()
+        };
+        if mess.connection(current).await.is_err() {
+            return;
+        }
+    }
+}
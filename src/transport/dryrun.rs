@@ -0,0 +1,183 @@
+// Dry run of a send
+// Walk the files the way import does and report what would happen,
+// without hashing hundreds of GB first.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use iroh_blobs::Hash;
+use iroh_blobs::api::Store;
+use iroh_blobs::api::blobs::BlobStatus;
+use n0_future::StreamExt;
+use tracing::warn;
+use walkdir::WalkDir;
+
+use super::offer::canonicalized_path_to_string;
+use crate::comms::MessageOut;
+
+// How many of the largest files to list
+const LARGEST_MAX: usize = 10;
+
+/// What a send of some paths would do.
+#[derive(Debug, Clone, Default)]
+pub struct DryRun {
+    pub total: u64,
+    pub files: usize,
+    /// Largest files first
+    pub largest: Vec<(String, u64)>,
+    /// Paths that would stop the import and why
+    pub rejected: Vec<(String, String)>,
+    /// Files already in the store , they are not copied again
+    pub deduplicated: Vec<(String, u64)>,
+}
+
+impl DryRun {
+    pub fn deduplicated_size(&self) -> u64 {
+        self.deduplicated.iter().map(|(_, size)| *size).sum()
+    }
+}
+
+/// Walk the paths with the import naming rules and check them against the store.
+///
+/// Only files with the same size as a blob in the store are hashed,
+/// everything else can not be a duplicate.
+pub async fn dry_run(paths: &[PathBuf], store: &Store, mess: MessageOut) -> Result<DryRun> {
+    let mut report = DryRun::default();
+    let mut names: BTreeMap<String, (PathBuf, u64)> = BTreeMap::new();
+    for path in paths {
+        let path = path.canonicalize()?;
+        let root = path.parent().context("context get parent")?;
+        for entry in WalkDir::new(&path) {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    let path = err.path().unwrap_or(&path).display().to_string();
+                    report.rejected.push((path, err.to_string()));
+                    continue;
+                }
+            };
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let name = match entry
+                .path()
+                .strip_prefix(root)
+                .map_err(anyhow::Error::from)
+                .and_then(|relative| canonicalized_path_to_string(relative, true))
+            {
+                Ok(name) => name,
+                Err(err) => {
+                    report
+                        .rejected
+                        .push((entry.path().display().to_string(), err.to_string()));
+                    continue;
+                }
+            };
+            let size = match entry.metadata() {
+                Ok(meta) => meta.len(),
+                Err(err) => {
+                    report.rejected.push((name, err.to_string()));
+                    continue;
+                }
+            };
+            if names.contains_key(&name) {
+                report
+                    .rejected
+                    .push((name, "is in the share twice".to_string()));
+                continue;
+            }
+            names.insert(name, (entry.into_path(), size));
+        }
+    }
+    report.files = names.len();
+    report.total = names.values().map(|(_, size)| *size).sum();
+    let mut largest = names
+        .iter()
+        .map(|(name, (_, size))| (name.clone(), *size))
+        .collect::<Vec<_>>();
+    largest.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    largest.truncate(LARGEST_MAX);
+    report.largest = largest;
+
+    // Hash the files that could be in the store
+    let sizes = stored_sizes(store).await?;
+    let candidates = names
+        .into_iter()
+        .filter(|(_, (_, size))| sizes.contains(size))
+        .collect::<Vec<_>>();
+    if !candidates.is_empty() {
+        mess.info(format!("Checking {} file(s) against the store", candidates.len()).as_str())
+            .await?;
+    }
+    for (name, (path, size)) in candidates {
+        let hash = match tokio::task::spawn_blocking(move || hash_file(&path)).await? {
+            Ok(hash) => hash,
+            Err(err) => {
+                warn!("could not hash {name} {err}");
+                continue;
+            }
+        };
+        if store.blobs().has(hash).await? {
+            report.deduplicated.push((name, size));
+        }
+    }
+    Ok(report)
+}
+
+// Sizes of the complete blobs in the store
+async fn stored_sizes(store: &Store) -> Result<HashSet<u64>> {
+    let mut sizes = HashSet::new();
+    let mut hashes = store.blobs().list().stream().await?;
+    while let Some(hash) = hashes.next().await {
+        if let BlobStatus::Complete { size } = store.blobs().status(hash?).await? {
+            sizes.insert(size);
+        }
+    }
+    Ok(sizes)
+}
+
+// Same hash the store would give the file
+fn hash_file(path: &Path) -> Result<Hash> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(std::fs::File::open(path)?)?;
+    Ok(hasher.finalize().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::memory_store;
+
+    #[tokio::test]
+    async fn names_duplicates_and_store_matches() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("sendme-dryrun-{}", std::process::id()));
+        let root = dir.join("docs");
+        std::fs::create_dir_all(root.join("sub"))?;
+        std::fs::write(root.join("a.txt"), b"already stored")?;
+        std::fs::write(root.join("sub").join("b.txt"), b"new and longer file")?;
+        let store = memory_store();
+        store.add_bytes(b"already stored".to_vec()).await?;
+        let (event_tx, _event_rx) = async_channel::unbounded();
+        let mess = MessageOut::new(event_tx);
+
+        // The same folder twice gives every name twice
+        let report = dry_run(&[root.clone(), root.clone()], &store, mess).await;
+        std::fs::remove_dir_all(&dir)?;
+        let report = report?;
+
+        assert_eq!(report.files, 2);
+        assert_eq!(report.total, 14 + 19);
+        assert_eq!(report.largest[0], ("docs/sub/b.txt".to_string(), 19));
+        assert_eq!(report.largest[1], ("docs/a.txt".to_string(), 14));
+        let twice = report
+            .rejected
+            .iter()
+            .filter(|(_, why)| why == "is in the share twice")
+            .count();
+        assert_eq!(twice, 2);
+        assert_eq!(report.deduplicated, vec![("docs/a.txt".to_string(), 14)]);
+        assert_eq!(report.deduplicated_size(), 14);
+        Ok(())
+    }
+}
@@ -1,9 +1,18 @@
+use super::diag::ConnectionWatch;
+use super::gc::Busy;
+use super::limit::Limits;
+use super::meta::{CollectionMeta, meta_index};
+use super::net::NetOptions;
+use super::tags::{INCOMING, delete_tag, new_tag, referenced_hashes, root_name};
+use super::ticket::clean_ticket;
+use super::verify::record_source;
 use crate::comms::MessageOut;
 use anyhow::Result;
 use anyhow::anyhow;
-use chrono::Local;
 use humansize::{DECIMAL, format_size};
+use iroh_blobs::Hash;
 use iroh_blobs::api::Store;
+use iroh_blobs::api::blobs::BlobStatus;
 use iroh_blobs::api::blobs::ExportMode;
 use iroh_blobs::api::blobs::ExportOptions;
 use iroh_blobs::api::blobs::ExportProgressItem;
@@ -11,37 +20,136 @@ use iroh_blobs::api::remote::GetProgressItem;
 use iroh_blobs::format::collection::Collection;
 use iroh_blobs::get::Stats;
 use iroh_blobs::get::request::get_hash_seq_and_sizes;
-use iroh_blobs::store::fs::FsStore;
+use iroh_blobs::protocol::{ChunkRanges, GetRequest};
 use iroh_blobs::ticket::BlobTicket;
 use n0_future::StreamExt;
+use std::collections::HashSet;
 use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
 use tracing::{info, warn};
 
-use iroh::{Endpoint, RelayMode, discovery::dns::DnsDiscovery};
+use iroh::{Endpoint, discovery::dns::DnsDiscovery};
 
-// fetch a blob from the iroh network
-pub async fn receive(ticket: String, target: PathBuf, mess: MessageOut, db: FsStore) -> Result<()> {
-    // TODO extract hash,node version of this , make ticket processing separate.
-    if ticket == "".to_string() {
-        return Err(anyhow!("Empty Blob"));
+/// Per fetch settings chosen in the gui.
+#[derive(Debug, Clone, Copy)]
+pub struct FetchOptions {
+    /// Keep the downloaded blobs in the store after the export.
+    pub keep_in_store: bool,
+    /// The store is in memory , leave no tags behind.
+    pub ephemeral: bool,
+    /// Download limit of this fetch in bytes per second , zero is none.
+    pub download_limit: u64,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self {
+            keep_in_store: true,
+            ephemeral: false,
+            download_limit: 0,
+        }
+    }
+}
+
+/// What is behind a ticket , names and sizes without the file data.
+#[derive(Debug, Clone)]
+pub struct Preview {
+    pub hash: Hash,
+    pub files: Vec<(String, u64)>,
+    pub total: u64,
+}
+
+// Accepts the "sendme receive" leader and .sendme file contents
+fn parse_ticket(ticket: &str) -> Result<BlobTicket> {
+    let ticket = clean_ticket(ticket);
+    if ticket.is_empty() {
+        return Err(anyhow!("No ticket found"));
     }
-    // TODO check for "sendme recieve" leader on the ticket.
-    let ticket = BlobTicket::from_str(ticket.as_str())?;
+    Ok(BlobTicket::from_str(ticket.as_str())?)
+}
 
-    // TODO move these up into the worker, move as an Option into the worker and pass and endpoint.
-    let addr = ticket.node_addr().clone();
+// TODO move these up into the worker, move as an Option into the worker and pass and endpoint.
+async fn client_endpoint(mess: &MessageOut, net: &NetOptions) -> Result<Endpoint> {
     let secret_key = super::get_or_create_secret()?;
-    let mut builder = Endpoint::builder()
-        .alpns(vec![])
-        .secret_key(secret_key)
-        .relay_mode(RelayMode::Default);
+    let mut builder = net.client_builder().alpns(vec![]).secret_key(secret_key);
 
     builder = builder.add_discovery(DnsDiscovery::n0_dns());
 
     let endpoint = builder.bind().await?;
     mess.info("Local endpoint created...").await?;
+    Ok(endpoint)
+}
+
+/// Get the file list of a ticket, only the collection itself is downloaded.
+pub async fn preview(
+    ticket: String,
+    net: &NetOptions,
+    mess: MessageOut,
+    db: Store,
+) -> Result<Preview> {
+    let ticket = parse_ticket(&ticket)?;
+    let hash = ticket.hash();
+    let local = db.remote().local(ticket.hash_and_format()).await?;
+    let sizes = if local.is_complete() {
+        None
+    } else {
+        let endpoint = client_endpoint(&mess, net).await?;
+        let connection = endpoint
+            .connect(ticket.node_addr().clone(), iroh_blobs::protocol::ALPN)
+            .await?;
+        mess.correct("Connection Established").await?;
+        let watch =
+            ConnectionWatch::start(&endpoint, ticket.node_addr().node_id, "fetch", mess.clone());
+        let (_hash_seq, sizes) =
+            get_hash_seq_and_sizes(&connection, &hash, 1024 * 1024 * 32, None).await?;
+        // The names are in the first child
+        let request = GetRequest::builder()
+            .root(ChunkRanges::all())
+            .child(0, ChunkRanges::all())
+            .build(hash);
+        db.remote()
+            .execute_get(connection, request)
+            .complete()
+            .await?;
+        watch.finish().await;
+        Some(sizes)
+    };
+    let collection = Collection::load(hash, &db).await?;
+    let meta = meta_index(&collection);
+    let mut files = Vec::new();
+    for (i, (name, child)) in collection.iter().enumerate() {
+        if Some(i) == meta {
+            continue;
+        }
+        // sizes has the names blob first
+        let size = match &sizes {
+            Some(sizes) => sizes.get(i + 1).copied().unwrap_or_default(),
+            None => match db.blobs().status(*child).await? {
+                BlobStatus::Complete { size } => size,
+                _ => 0,
+            },
+        };
+        files.push((name.clone(), size));
+    }
+    let total = files.iter().map(|(_, size)| size).sum();
+    Ok(Preview { hash, files, total })
+}
+
+// fetch a blob from the iroh network
+pub async fn receive(
+    ticket: String,
+    target: PathBuf,
+    options: FetchOptions,
+    net: &NetOptions,
+    limits: &Limits,
+    mess: MessageOut,
+    db: Store,
+) -> Result<()> {
+    // TODO extract hash,node version of this , make ticket processing separate.
+    let ticket = parse_ticket(&ticket)?;
+    let addr = ticket.node_addr().clone();
+    let endpoint = client_endpoint(&mess, net).await?;
 
     warn!("Node built");
 
@@ -51,10 +159,19 @@ pub async fn receive(ticket: String, target: PathBuf, mess: MessageOut, db: FsSt
         info!("computing local");
         let local = db.remote().local(hash_and_format).await?;
         info!("got local");
+        // Tag of the new download , None if it was here already
+        let mut incoming = None;
         let (stats, total_files, payload_size) = if !local.is_complete() {
             mess.info("Unfinished Download...").await?;
+            // Keep the gc off the partial download
+            let busy = match options.ephemeral {
+                true => None,
+                false => Some(Busy::protect(&db, "fetch", hash_and_format.hash).await?),
+            };
+            let node_id = addr.node_id;
             let connection = endpoint.connect(addr, iroh_blobs::protocol::ALPN).await?;
             mess.correct("Connection Established").await?;
+            let watch = ConnectionWatch::start(&endpoint, node_id, "fetch", mess.clone());
             let (_hash_seq, sizes) =
                 get_hash_seq_and_sizes(&connection, &hash_and_format.hash, 1024 * 1024 * 32, None)
                     .await?;
@@ -73,10 +190,15 @@ pub async fn receive(ticket: String, target: PathBuf, mess: MessageOut, db: FsSt
             // Fetch the file
             let get = db.remote().execute_get(connection, local.missing());
             let stats = Stats::default();
+            // Holding back the progress holds back the download
+            let throttle = limits.fetching();
+            let mut read = 0;
             let mut stream = get.stream();
             while let Some(item) = stream.next().await {
                 match item {
                     GetProgressItem::Progress(offset) => {
+                        throttle.take(offset - read).await;
+                        read = offset;
                         mess.progress("Download", offset as usize, payload_size as usize)
                             .await?;
                     }
@@ -91,10 +213,15 @@ pub async fn receive(ticket: String, target: PathBuf, mess: MessageOut, db: FsSt
                     }
                 }
             }
+            watch.finish().await;
 
             // Set a tag for later work, full replica
-            let dt = Local::now().to_rfc3339().to_owned();
-            db.tags().set(format!("incoming-{}", dt), ticket.hash()).await?;
+            if !options.ephemeral {
+                incoming = Some(tag_incoming(&db, hash_and_format.hash).await?);
+            }
+            if let Some(busy) = busy {
+                busy.release().await?;
+            }
             (stats, total_files, payload_size)
         } else {
             // Have it already , just say yes.
@@ -103,10 +230,8 @@ pub async fn receive(ticket: String, target: PathBuf, mess: MessageOut, db: FsSt
             let payload_bytes = 0;
             (Stats::default(), total_files, payload_bytes)
         };
-        // Save the collection.
-        let collection = Collection::load(hash_and_format.hash, db.as_ref()).await?;
         // Eport is instrinsic for now , split it out ongoing.
-        export(&db, collection, target, mess.clone()).await?;
+        export_fetched(&db, hash_and_format.hash, incoming, target, options, &mess).await?;
         (stats, total_files, payload_size)
     };
 
@@ -117,15 +242,73 @@ pub async fn receive(ticket: String, target: PathBuf, mess: MessageOut, db: FsSt
     Ok(())
 }
 
+// Tag a new download , the tag keeps it from the gc
+pub(super) async fn tag_incoming(db: &Store, hash: Hash) -> Result<String> {
+    let collection = Collection::load(hash, db).await?;
+    new_tag(db, INCOMING, &root_name(&collection), hash).await
+}
+
+// Write a finished download out to `target`.
+// `incoming` is the tag of a new download , it goes when the store is not keeping it.
+pub(super) async fn export_fetched(
+    db: &Store,
+    hash: Hash,
+    incoming: Option<String>,
+    target: PathBuf,
+    options: FetchOptions,
+    mess: &MessageOut,
+) -> Result<()> {
+    let collection = Collection::load(hash, db).await?;
+    // Move the files out if the store is not keeping them and nothing else uses them
+    let movable = match &incoming {
+        Some(tag) if !options.keep_in_store => {
+            let in_use = referenced_hashes(db, tag).await?;
+            collection
+                .iter()
+                .map(|(_, hash)| *hash)
+                .filter(|hash| !in_use.contains(hash))
+                .collect()
+        }
+        _ => HashSet::new(),
+    };
+    export(db, collection, target, movable, mess.clone()).await?;
+    if let Some(tag) = incoming
+        && !options.keep_in_store
+    {
+        delete_tag(db, &tag).await?;
+        mess.info("Download removed from the store").await?;
+    }
+    Ok(())
+}
+
+/// Export a collection that is already in the store.
+pub async fn export_stored(
+    db: &Store,
+    hash: Hash,
+    target_dir: PathBuf,
+    mess: MessageOut,
+) -> Result<()> {
+    let collection = Collection::load(hash, db).await?;
+    export(db, collection, target_dir, HashSet::new(), mess).await
+}
+
 // Take the blob and make real files.
+// Blobs in `movable` are moved out of the store when the filesystem allows it.
 pub async fn export(
     db: &Store,
     collection: Collection,
     target_dir: PathBuf,
+    mut movable: HashSet<Hash>,
     mess: MessageOut,
 ) -> Result<()> {
     let len = collection.len();
+    let meta = CollectionMeta::load(&collection, db).await;
+    let meta_at = meta_index(&collection);
     for (i, (name, hash)) in collection.iter().enumerate() {
+        // Metadata is applied after the files are out
+        if Some(i) == meta_at {
+            continue;
+        }
         // info!("file name {}", name);
         let target = get_export_path(&target_dir, name)?;
         info!("target {:#?}", target.display());
@@ -137,12 +320,18 @@ pub async fn export(
             anyhow::bail!("{} already exists", target.display());
         }
         mess.progress("Export", i + 1, len).await?;
+        // A moved blob lives at the first target , copy any repeats
+        let moved = movable.remove(hash);
         // Get a stream of the files to download
         let mut stream = db
             .export_with_opts(ExportOptions {
                 hash: *hash,
-                target,
-                mode: ExportMode::Copy,
+                target: target.clone(),
+                mode: if moved {
+                    ExportMode::TryReference
+                } else {
+                    ExportMode::Copy
+                },
             })
             .stream()
             .await;
@@ -164,13 +353,21 @@ pub async fn export(
                 }
             }
         }
+        // The store may now read the blob from the exported file
+        if moved {
+            record_source(db, *hash, &target).await?;
+        }
+    }
+    if let Some(meta) = meta {
+        meta.apply(&target_dir)?;
+        mess.info("File times and permissions restored").await?;
     }
     mess.complete("Export").await?;
     Ok(())
 }
 
 // Path cheking an manipulation ( extracted from sendme)
-fn get_export_path(root: &Path, name: &str) -> anyhow::Result<PathBuf> {
+pub(super) fn get_export_path(root: &Path, name: &str) -> anyhow::Result<PathBuf> {
     let parts = name.split('/');
     let mut path = root.to_path_buf();
     for part in parts {
@@ -187,4 +384,4 @@ fn validate_path_component(component: &str) -> anyhow::Result<()> {
         "path components must not contain the only correct path separator, /"
     );
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,375 @@
+// Garbage collection for the blob store
+// Every fetch leaves a full copy in the store, this keeps it in check.
+//
+// Tags are the roots , expiry and the quota remove old `incoming` tags
+// and then the iroh-blobs gc sweeps whatever is no longer reachable.
+// `pin-` and `seed-` tags keep a collection forever, `busy-` tags protect
+// what is being served or fetched right now. The `ref-` tags of swept
+// blobs are dropped after the sweep.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Local;
+use iroh_blobs::api::Store;
+use iroh_blobs::format::collection::Collection;
+use iroh_blobs::store::fs::options::{GcConfig, ProtectCb, ProtectOutcome};
+use iroh_blobs::{Hash, HashAndFormat};
+use n0_future::StreamExt;
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+use walkdir::WalkDir;
+
+use super::tags::{INCOMING, TagEntry, delete_tag, list_tags};
+use super::verify::drop_stale_sources;
+
+// Prefix of the tags that keep a collection out of expiry and the quota
+pub const PIN_PREFIX: &str = "pin-";
+// Prefix of the tags that protect running shares and fetches
+pub const BUSY_PREFIX: &str = "busy-";
+
+// Pause between the end of a gc run and waiting for the next request
+const GC_PAUSE: Duration = Duration::from_secs(1);
+
+/// Limits for the store , zero switches a limit off.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GcOptions {
+    /// Largest the store folder may get (bytes)
+    pub max_store_size: u64,
+    /// Incoming collections older than this are removed (days)
+    pub incoming_expiry_days: u64,
+    /// Time between scheduled runs (hours)
+    pub interval_hours: u64,
+}
+
+/// What a clean up did.
+#[derive(Debug, Clone)]
+pub struct GcReport {
+    pub before: u64,
+    pub after: u64,
+    pub expired: usize,
+    pub evicted: usize,
+}
+
+impl GcReport {
+    pub fn reclaimed(&self) -> u64 {
+        self.before.saturating_sub(self.after)
+    }
+}
+
+// A request for a gc run , answered when the run is over
+type GcRequest = oneshot::Sender<()>;
+
+/// Runs the iroh-blobs gc when asked instead of on a timer.
+///
+/// The store calls the protect callback before every run, the callback
+/// waits here until a run is requested.
+#[derive(Clone)]
+pub struct Collector {
+    requests: async_channel::Sender<GcRequest>,
+}
+
+impl Collector {
+    /// The gc config for the store and the handle to trigger it.
+    pub fn new() -> (GcConfig, Self) {
+        let (requests, incoming) = async_channel::unbounded::<GcRequest>();
+        // The request of the run in progress
+        let running = Arc::new(Mutex::new(None::<GcRequest>));
+        let add_protected: ProtectCb = Arc::new(move |_live: &mut HashSet<Hash>| {
+            let incoming = incoming.clone();
+            let running = running.clone();
+            Box::pin(async move {
+                // Being called again means the last run is finished
+                if let Some(done) = running.lock().expect("gc lock").take() {
+                    let _ = done.send(());
+                }
+                let Ok(request) = incoming.recv().await else {
+                    // Nobody can ask anymore
+                    return std::future::pending().await;
+                };
+                *running.lock().expect("gc lock") = Some(request);
+                ProtectOutcome::Continue
+            })
+        });
+        let config = GcConfig {
+            interval: GC_PAUSE,
+            add_protected: Some(add_protected),
+        };
+        (config, Self { requests })
+    }
+
+    // Run the gc once and wait for it to finish
+    async fn run(&self) -> Result<()> {
+        let (done_tx, done_rx) = oneshot::channel();
+        self.requests.send(done_tx).await?;
+        done_rx
+            .await
+            .map_err(|_| anyhow::anyhow!("garbage collection has stopped"))?;
+        Ok(())
+    }
+}
+
+/// Apply expiry and the quota and then sweep the store.
+pub async fn collect_garbage(
+    store: &Store,
+    store_path: &Path,
+    collector: &Collector,
+    options: GcOptions,
+) -> Result<GcReport> {
+    let before = disk_usage(store_path);
+    let pins = pinned(store).await?;
+    let mut incoming = list_tags(store, INCOMING)
+        .await?
+        .into_iter()
+        .filter(|entry| !pins.contains(&entry.tag) && !entry.seeded)
+        .collect::<Vec<_>>();
+    // oldest first
+    incoming.reverse();
+
+    let mut expired = 0;
+    if options.incoming_expiry_days > 0 {
+        let cutoff = Local::now() - chrono::Duration::days(options.incoming_expiry_days as i64);
+        let (old, keep): (Vec<TagEntry>, Vec<TagEntry>) = incoming
+            .into_iter()
+            .partition(|entry| entry.created.is_some_and(|created| created < cutoff));
+        for entry in old {
+            info!("expired {}", entry.tag);
+            delete_tag(store, &entry.tag).await?;
+            expired += 1;
+        }
+        incoming = keep;
+    }
+
+    // Blobs shared between collections make this a guess , the next run catches up
+    let mut evicted = 0;
+    if options.max_store_size > 0 && before > options.max_store_size {
+        let mut over = before - options.max_store_size;
+        for entry in incoming {
+            if over == 0 {
+                break;
+            }
+            info!("over quota , removing {}", entry.tag);
+            delete_tag(store, &entry.tag).await?;
+            over = over.saturating_sub(entry.size);
+            evicted += 1;
+        }
+        if over > 0 {
+            warn!("store is still over quota , only pinned, seeded and outgoing data is left");
+        }
+    }
+
+    collector.run().await?;
+    // The paths of the swept blobs go with the next run
+    drop_stale_sources(store).await?;
+    Ok(GcReport {
+        before,
+        after: disk_usage(store_path),
+        expired,
+        evicted,
+    })
+}
+
+/// Keep a tagged collection out of expiry and the quota.
+pub async fn pin_tag(store: &Store, tag: &str, hash: Hash) -> Result<()> {
+    store
+        .tags()
+        .set(format!("{PIN_PREFIX}{tag}"), HashAndFormat::hash_seq(hash))
+        .await?;
+    Ok(())
+}
+
+pub async fn unpin_tag(store: &Store, tag: &str) -> Result<()> {
+    store.tags().delete(format!("{PIN_PREFIX}{tag}")).await?;
+    Ok(())
+}
+
+/// Names of the pinned tags.
+pub async fn pinned(store: &Store) -> Result<HashSet<String>> {
+    let mut tags = store.tags().list_prefix(PIN_PREFIX).await?;
+    let mut pins = HashSet::new();
+    while let Some(info) = tags.next().await {
+        let name = String::from_utf8_lossy(&info?.name.0).to_string();
+        if let Some(tag) = name.strip_prefix(PIN_PREFIX) {
+            pins.insert(tag.to_string());
+        }
+    }
+    Ok(pins)
+}
+
+/// Keeps a collection from being swept while a job uses it.
+///
+/// Every job gets its own tag so two jobs on the same collection do not
+/// release each other. Dropped without `release` , on an error path , the
+/// tag is removed in the background.
+pub struct Busy {
+    store: Store,
+    tag: Option<String>,
+}
+
+impl Busy {
+    pub async fn protect(store: &Store, job: &str, hash: Hash) -> Result<Self> {
+        static JOBS: AtomicU64 = AtomicU64::new(0);
+        let id = JOBS.fetch_add(1, Ordering::Relaxed);
+        let tag = format!("{BUSY_PREFIX}{job}-{hash}-{id}");
+        store
+            .tags()
+            .set(tag.as_str(), HashAndFormat::hash_seq(hash))
+            .await?;
+        Ok(Self {
+            store: store.clone(),
+            tag: Some(tag),
+        })
+    }
+
+    pub async fn release(mut self) -> Result<()> {
+        if let Some(tag) = self.tag.take() {
+            self.store.tags().delete(tag).await?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        let Some(tag) = self.tag.take() else {
+            return;
+        };
+        let store = self.store.clone();
+        // Left over tags are cleared on the next start otherwise
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                if let Err(err) = store.tags().delete(tag.as_str()).await {
+                    warn!("failed to release {tag} {err}");
+                }
+            });
+        }
+    }
+}
+
+/// Get the store ready for gc.
+///
+/// Drops protection left over from the last run and turns the old raw
+/// tags on collections into hash sequence tags, a raw tag only keeps the
+/// collection blob and not the files in it.
+pub async fn prepare_store(store: &Store) -> Result<()> {
+    store.tags().delete_prefix(BUSY_PREFIX).await?;
+    let mut tags = store.tags().list().await?;
+    let mut raw = Vec::new();
+    while let Some(info) = tags.next().await {
+        let info = info?;
+        if info.format.is_raw() {
+            raw.push(info);
+        }
+    }
+    for info in raw {
+        if Collection::load(info.hash, store).await.is_ok() {
+            store
+                .tags()
+                .set(info.name, HashAndFormat::hash_seq(info.hash))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Space used by the store folder.
+pub fn disk_usage(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|meta| meta.is_file())
+        .map(|meta| meta.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::memory_store;
+    use crate::transport::verify::{REF_PREFIX, record_source};
+
+    async fn busy_tags(store: &Store) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        let mut tags = store.tags().list_prefix(BUSY_PREFIX).await?;
+        while let Some(info) = tags.next().await {
+            names.push(String::from_utf8_lossy(&info?.name.0).to_string());
+        }
+        Ok(names)
+    }
+
+    #[tokio::test]
+    async fn busy_tags_per_job_and_released_on_drop() -> Result<()> {
+        let store = memory_store();
+        let hash = Hash::new(b"collection");
+        let first = Busy::protect(&store, "share", hash).await?;
+        let second = Busy::protect(&store, "share", hash).await?;
+        assert_eq!(busy_tags(&store).await?.len(), 2);
+
+        first.release().await?;
+        assert_eq!(busy_tags(&store).await?.len(), 1);
+
+        // An error path drops the guard
+        drop(second);
+        for _ in 0..100 {
+            if busy_tags(&store).await?.is_empty() {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        anyhow::bail!("dropped protection was not released");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn expiry_removes_the_info_tag() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("sendme-gc-expiry-{}", std::process::id()));
+        let (store, collector) = crate::transport::open_store(&path).await?;
+        let tag = "incoming/old-2020-01-01";
+        let hash = store.add_bytes(b"old download".to_vec()).await?.hash;
+        store.tags().set(tag, HashAndFormat::raw(hash)).await?;
+        let info = store
+            .add_bytes(br#"{"created":"2020-01-01T00:00:00+00:00","note":""}"#.to_vec())
+            .await?
+            .hash;
+        store.tags().set(format!("info-{tag}"), info).await?;
+
+        let options = GcOptions {
+            incoming_expiry_days: 30,
+            ..Default::default()
+        };
+        let report = collect_garbage(&store, &path, &collector, options).await?;
+        assert_eq!(report.expired, 1);
+        let mut left = store.tags().list_prefix("info-").await?;
+        assert!(left.next().await.is_none());
+
+        store.shutdown().await?;
+        std::fs::remove_dir_all(&path)?;
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sources_of_swept_blobs_are_dropped() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("sendme-gc-sources-{}", std::process::id()));
+        let (store, collector) = crate::transport::open_store(&path).await?;
+        let kept = store.add_bytes(b"kept".to_vec()).await?.hash;
+        store.tags().set("outgoing/kept", kept).await?;
+        record_source(&store, kept, Path::new("/data/kept")).await?;
+        record_source(&store, Hash::new(b"swept"), Path::new("/data/swept")).await?;
+
+        collect_garbage(&store, &path, &collector, GcOptions::default()).await?;
+        let mut names = Vec::new();
+        let mut tags = store.tags().list_prefix(REF_PREFIX).await?;
+        while let Some(info) = tags.next().await {
+            names.push(String::from_utf8_lossy(&info?.name.0).to_string());
+        }
+        assert_eq!(names, vec![format!("{REF_PREFIX}{kept}")]);
+
+        store.shutdown().await?;
+        std::fs::remove_dir_all(&path)?;
+        Ok(())
+    }
+}
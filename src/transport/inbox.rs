@@ -0,0 +1,282 @@
+// Inbox
+// Collections pushed straight to a contact instead of a ticket.
+//
+// The sender offers a collection over INBOX_ALPN to the seeding node of
+// the receiver , the hash , name and size with the address to fetch
+// from. The receiver is asked in the gui and the answer goes back on the
+// same stream. Once accepted the gui runs the normal fetch against the
+// seeding node of the sender , it serves what it offered.
+//
+// Answers and the allowed senders go through the shared `Inbox` so the
+// gui can take an offer while a fetch holds the command loop of the
+// worker. Senders that are not allowed are turned away before a stream
+// is opened.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use iroh::endpoint::{Connection, RecvStream};
+use iroh::protocol::{AcceptError, ProtocolHandler};
+use iroh::{Endpoint, NodeAddr, NodeId, Watcher};
+use iroh_blobs::ticket::BlobTicket;
+use iroh_blobs::{BlobFormat, Hash};
+use serde_derive::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+use crate::comms::MessageOut;
+
+/// Protocol for offering a collection to another node.
+pub const INBOX_ALPN: &[u8] = b"sendme-egui/inbox/0";
+// Offers and answers are small json messages
+const MAX_MESSAGE: usize = 64 * 1024;
+// An offer nobody answers is declined
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// What is offered , shown before anything is fetched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Offer {
+    pub hash: Hash,
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OfferRequest {
+    offer: Offer,
+    // Where to fetch from , the receiver may not find it by id alone
+    from: NodeAddr,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum OfferResponse {
+    Accepted,
+    Declined,
+    Failed(String),
+}
+
+/// An offer waiting for an answer in the gui.
+#[derive(Debug, Clone)]
+pub struct IncomingOffer {
+    pub id: u64,
+    pub from: NodeId,
+    pub offer: Offer,
+    /// Fetches the collection from the sender
+    pub ticket: String,
+}
+
+/// Whether offers are taken and the ones waiting for an answer.
+#[derive(Debug, Clone, Default)]
+pub struct Inbox(Arc<InboxInner>);
+
+#[derive(Debug, Default)]
+struct InboxInner {
+    listen: AtomicBool,
+    contacts_only: AtomicBool,
+    contacts: Mutex<HashSet<NodeId>>,
+    next: AtomicU64,
+    pending: Mutex<HashMap<u64, oneshot::Sender<bool>>>,
+}
+
+impl Inbox {
+    pub fn set_listen(&self, listen: bool) {
+        self.0.listen.store(listen, Ordering::Relaxed);
+    }
+
+    pub fn listening(&self) -> bool {
+        self.0.listen.load(Ordering::Relaxed)
+    }
+
+    /// Who may offer , only `contacts` or anyone.
+    pub fn set_senders(&self, contacts_only: bool, contacts: HashSet<NodeId>) {
+        *self.0.contacts.lock().expect("inbox lock") = contacts;
+        self.0.contacts_only.store(contacts_only, Ordering::Relaxed);
+    }
+
+    fn allows(&self, peer: NodeId) -> bool {
+        !self.0.contacts_only.load(Ordering::Relaxed)
+            || self.0.contacts.lock().expect("inbox lock").contains(&peer)
+    }
+
+    /// Accept or decline an offer , false if it is no longer waiting.
+    pub fn answer(&self, id: u64, accept: bool) -> bool {
+        match self.0.pending.lock().expect("inbox lock").remove(&id) {
+            Some(answer) => answer.send(accept).is_ok(),
+            None => false,
+        }
+    }
+
+    fn wait(&self) -> (u64, oneshot::Receiver<bool>) {
+        let id = self.0.next.fetch_add(1, Ordering::Relaxed);
+        let (answer, answered) = oneshot::channel();
+        self.0
+            .pending
+            .lock()
+            .expect("inbox lock")
+            .insert(id, answer);
+        (id, answered)
+    }
+
+    fn forget(&self, id: u64) {
+        self.0.pending.lock().expect("inbox lock").remove(&id);
+    }
+}
+
+/// Offer a collection to `to` , true when it was accepted.
+pub async fn push_offer(endpoint: &Endpoint, to: NodeAddr, offer: Offer) -> Result<bool> {
+    let from = endpoint.node_addr().initialized().await;
+    let peer = to.node_id;
+    let connection = endpoint.connect(to, INBOX_ALPN).await?;
+    let (mut send, mut recv) = connection.open_bi().await?;
+    let request = serde_json::to_vec(&OfferRequest { offer, from })?;
+    send.write_all(&request).await?;
+    send.finish()?;
+    let response = recv.read_to_end(MAX_MESSAGE).await?;
+    connection.close(0u32.into(), b"done");
+    match serde_json::from_slice(&response)? {
+        OfferResponse::Accepted => Ok(true),
+        OfferResponse::Declined => Ok(false),
+        OfferResponse::Failed(err) => Err(anyhow!("{} : {}", peer.fmt_short(), err)),
+    }
+}
+
+// The receiver side , hands offers to the gui and waits for the answer
+#[derive(Clone)]
+pub(super) struct InboxService {
+    pub(super) inbox: Inbox,
+    pub(super) mess: MessageOut,
+}
+
+impl fmt::Debug for InboxService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InboxService")
+            .field("inbox", &self.inbox)
+            .finish()
+    }
+}
+
+impl ProtocolHandler for InboxService {
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let peer = connection.remote_node_id()?;
+        if !self.inbox.listening() {
+            warn!("offer from {peer} while the inbox is closed");
+            return Err(AcceptError::NotAllowed {});
+        }
+        if !self.inbox.allows(peer) {
+            warn!("offer from unknown node {peer} declined");
+            return Err(AcceptError::NotAllowed {});
+        }
+        let (mut send, mut recv) = connection.accept_bi().await?;
+        let response = match self.take_offer(&mut recv, peer).await {
+            Ok(true) => OfferResponse::Accepted,
+            Ok(false) => OfferResponse::Declined,
+            Err(err) => {
+                warn!("offer from {peer} failed {err}");
+                OfferResponse::Failed(err.to_string())
+            }
+        };
+        let response = serde_json::to_vec(&response).map_err(AcceptError::from_err)?;
+        send.write_all(&response)
+            .await
+            .map_err(AcceptError::from_err)?;
+        send.finish().map_err(AcceptError::from_err)?;
+        connection.closed().await;
+        Ok(())
+    }
+}
+
+impl InboxService {
+    async fn take_offer(&self, recv: &mut RecvStream, peer: NodeId) -> Result<bool> {
+        let request: OfferRequest = serde_json::from_slice(&recv.read_to_end(MAX_MESSAGE).await?)?;
+        anyhow::ensure!(request.from.node_id == peer, "offered from another node");
+        info!(
+            "offer of {} from {}",
+            request.offer.hash.fmt_short(),
+            peer.fmt_short()
+        );
+        let ticket = BlobTicket::new(request.from, request.offer.hash, BlobFormat::HashSeq);
+        let (id, answered) = self.inbox.wait();
+        self.mess
+            .offer(IncomingOffer {
+                id,
+                from: peer,
+                offer: request.offer,
+                ticket: ticket.to_string(),
+            })
+            .await?;
+        match tokio::time::timeout(ANSWER_TIMEOUT, answered).await {
+            Ok(Ok(accept)) => Ok(accept),
+            // Not answered in time or the gui went away
+            _ => {
+                self.inbox.forget(id);
+                Ok(false)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::RelayMode;
+    use iroh::protocol::Router;
+
+    use super::*;
+    use crate::comms::Event;
+
+    async fn endpoint() -> Result<Endpoint> {
+        Ok(Endpoint::builder()
+            .relay_mode(RelayMode::Disabled)
+            .bind()
+            .await?)
+    }
+
+    fn offer() -> Offer {
+        Offer {
+            hash: Hash::new(b"collection"),
+            name: "docs".to_string(),
+            size: 5,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn offers_only_from_allowed_senders() -> Result<()> {
+        let (event_tx, event_rx) = async_channel::unbounded();
+        let inbox = Inbox::default();
+        let service = InboxService {
+            inbox: inbox.clone(),
+            mess: MessageOut::new(event_tx),
+        };
+        let receiver = endpoint().await?;
+        let to = receiver.node_addr().initialized().await;
+        let router = Router::builder(receiver)
+            .accept(INBOX_ALPN, service)
+            .spawn();
+        // Accept whatever reaches the gui
+        let answers = inbox.clone();
+        tokio::spawn(async move {
+            while let Ok(event) = event_rx.recv().await {
+                if let Event::Offer(offer) = event {
+                    answers.answer(offer.id, true);
+                }
+            }
+        });
+        let (friend, stranger) = (endpoint().await?, endpoint().await?);
+
+        // Closed
+        assert!(push_offer(&friend, to.clone(), offer()).await.is_err());
+        inbox.set_listen(true);
+        inbox.set_senders(true, HashSet::from([friend.node_id()]));
+        assert!(push_offer(&friend, to.clone(), offer()).await?);
+        assert!(push_offer(&stranger, to.clone(), offer()).await.is_err());
+        // Anyone
+        inbox.set_senders(false, HashSet::new());
+        assert!(push_offer(&stranger, to, offer()).await?);
+
+        router.shutdown().await?;
+        Ok(())
+    }
+}
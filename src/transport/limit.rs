@@ -0,0 +1,237 @@
+// Bandwidth limits
+// Upload and download rates , set from the config and changed live.
+//
+// iroh-blobs has no throttle so both sides pace themselves. A fetch waits
+// on its progress stream , that holds back the reads and through quic
+// flow control the sender too , which stays at most a receive window
+// ahead. Serving writes through a paced send stream , see provider.rs.
+//
+// A transfer is held to the shared limit of its direction and to the
+// limit of its job , whichever is tighter.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::{Instant, sleep_until};
+
+// Behind schedule by more than this is an idle link , start over
+// rather than let a burst through
+const SLACK: Duration = Duration::from_secs(1);
+
+/// A rate in bytes per second shared by every transfer that uses it.
+///
+/// Zero is no limit.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimit(Arc<RateInner>);
+
+#[derive(Debug, Default)]
+struct RateInner {
+    rate: AtomicU64,
+    // Start of the current run , bytes since and the rate it ran at
+    run: Mutex<Option<(Instant, u64, u64)>>,
+}
+
+impl RateLimit {
+    pub fn set(&self, rate: u64) {
+        self.0.rate.store(rate, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.rate.load(Ordering::Relaxed)
+    }
+
+    // When `len` more bytes may go , none if there is no limit
+    fn due(&self, len: u64) -> Option<Instant> {
+        let rate = self.get();
+        let mut run = self.0.run.lock().expect("rate lock");
+        if rate == 0 {
+            *run = None;
+            return None;
+        }
+        let now = Instant::now();
+        let (start, bytes) = match *run {
+            Some((start, bytes, at))
+                if at == rate && now <= schedule(start, bytes, rate) + SLACK =>
+            {
+                (start, bytes)
+            }
+            _ => (now, 0),
+        };
+        let bytes = bytes + len;
+        *run = Some((start, bytes, rate));
+        Some(schedule(start, bytes, rate))
+    }
+}
+
+fn schedule(start: Instant, bytes: u64, rate: u64) -> Instant {
+    start + Duration::from_secs_f64(bytes as f64 / rate as f64)
+}
+
+/// The shared limits and the limits of the running jobs.
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    pub upload: RateLimit,
+    pub download: RateLimit,
+    /// The running share
+    pub share: RateLimit,
+    /// The running fetch
+    pub fetch: RateLimit,
+}
+
+impl Limits {
+    pub(super) fn serving(&self) -> Throttle {
+        Throttle(vec![self.upload.clone(), self.share.clone()])
+    }
+
+    pub(super) fn seeding(&self) -> Throttle {
+        Throttle(vec![self.upload.clone()])
+    }
+
+    pub(super) fn fetching(&self) -> Throttle {
+        Throttle(vec![self.download.clone(), self.fetch.clone()])
+    }
+
+    pub(super) fn replicating(&self) -> Throttle {
+        Throttle(vec![self.download.clone()])
+    }
+}
+
+/// The limits one transfer is held to.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Throttle(Vec<RateLimit>);
+
+impl Throttle {
+    /// Wait until `len` bytes fit in every limit.
+    pub(super) async fn take(&self, len: u64) {
+        let due = self.0.iter().filter_map(|limit| limit.due(len)).max();
+        if let Some(due) = due {
+            sleep_until(due).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use iroh::endpoint::Connection;
+    use iroh::protocol::Router;
+    use iroh::{Endpoint, RelayMode, Watcher};
+    use iroh_blobs::Hash;
+    use iroh_blobs::api::remote::GetProgressItem;
+    use iroh_blobs::protocol::GetRequest;
+    use n0_future::StreamExt;
+
+    use super::*;
+    use crate::comms::MessageOut;
+    use crate::transport::memory_store;
+    use crate::transport::offer::blobs_router;
+
+    const SIZE: u64 = 6_000_000;
+    const RATE: u64 = 2_000_000;
+
+    #[test]
+    fn due_follows_the_rate() {
+        let limit = RateLimit::default();
+        assert!(limit.due(1000).is_none());
+
+        limit.set(1000);
+        let first = limit.due(500).unwrap();
+        let second = limit.due(500).unwrap();
+        assert_eq!(second - first, Duration::from_millis(500));
+
+        // A new rate starts a new run
+        limit.set(2000);
+        let before = Instant::now();
+        let third = limit.due(1000).unwrap();
+        assert!(third >= before + Duration::from_millis(500));
+        assert!(third < second);
+
+        limit.set(0);
+        assert!(limit.due(1000).is_none());
+    }
+
+    // A blob served on a local endpoint and a connection to it
+    async fn serve_blob(limits: &Limits) -> Result<(Router, Connection, Hash)> {
+        let store = memory_store();
+        let hash = store.add_bytes(vec![7u8; SIZE as usize]).await?.hash;
+        let (event_tx, _event_rx) = async_channel::unbounded();
+        let mess = MessageOut::new(event_tx);
+        let server = Endpoint::builder()
+            .relay_mode(RelayMode::Disabled)
+            .bind()
+            .await?;
+        let addr = server.node_addr().initialized().await;
+        let router =
+            blobs_router(&store, server, None, None, limits.serving(), &mess, "send").spawn();
+        let client = Endpoint::builder()
+            .relay_mode(RelayMode::Disabled)
+            .bind()
+            .await?;
+        let connection = client.connect(addr, iroh_blobs::ALPN).await?;
+        Ok((router, connection, hash))
+    }
+
+    // Fetch like fetch.rs does , the bytes on the wire after a second and
+    // the time it took
+    async fn fetch_blob(
+        connection: Connection,
+        hash: Hash,
+        throttle: Throttle,
+    ) -> Result<(u64, Duration)> {
+        let target = memory_store();
+        let start = Instant::now();
+        let mut stream = target
+            .remote()
+            .execute_get(connection.clone(), GetRequest::blob(hash))
+            .stream();
+        let mut read = 0;
+        let mut early = None;
+        while let Some(item) = stream.next().await {
+            match item {
+                GetProgressItem::Progress(offset) => {
+                    throttle.take(offset - read).await;
+                    read = offset;
+                }
+                GetProgressItem::Done(_) => break,
+                GetProgressItem::Error(err) => return Err(err.into()),
+            }
+            if early.is_none() && start.elapsed() >= Duration::from_secs(1) {
+                early = Some(connection.stats().udp_rx.bytes);
+            }
+        }
+        let early = early.expect("the fetch took over a second");
+        Ok((early, start.elapsed()))
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn download_limit_holds_back_the_sender() -> Result<()> {
+        let (router, connection, hash) = serve_blob(&Limits::default()).await?;
+        let limits = Limits::default();
+        limits.download.set(RATE);
+        let (early, elapsed) = fetch_blob(connection, hash, limits.fetching()).await?;
+        router.shutdown().await?;
+
+        // The sender may run a receive window ahead of the reads , not more
+        assert!(
+            early < RATE + 2_000_000,
+            "{early} bytes in the first second"
+        );
+        assert!(elapsed >= Duration::from_secs_f64(0.8 * SIZE as f64 / RATE as f64));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn upload_limit_paces_the_provider() -> Result<()> {
+        let limits = Limits::default();
+        limits.upload.set(RATE);
+        let (router, connection, hash) = serve_blob(&limits).await?;
+        let (early, elapsed) = fetch_blob(connection, hash, Throttle::default()).await?;
+        router.shutdown().await?;
+
+        assert!(early < RATE + 500_000, "{early} bytes in the first second");
+        assert!(elapsed >= Duration::from_secs_f64(0.9 * SIZE as f64 / RATE as f64));
+        assert!(elapsed < Duration::from_secs_f64(1.5 * SIZE as f64 / RATE as f64));
+        Ok(())
+    }
+}
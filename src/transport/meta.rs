@@ -0,0 +1,247 @@
+// File metadata that rides along with a collection.
+// A collection only holds names and hashes, this adds the things
+// that get lost on the way: mode bits, modification times and empty folders.
+//
+// It is stored as one extra json entry , the last in the collection and
+// named `.<root>.sendme-meta` after the shared root. Old receivers still
+// work , they do not know the entry and write it out as a small hidden
+// file next to the root. Only the exact entry is taken as metadata , a
+// real file with a name like it is exported as usual.
+//
+// Only the permission bits travel , setuid , setgid and sticky are
+// dropped when sending and again when applying.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use iroh_blobs::api::{Store, TempTag};
+use iroh_blobs::format::collection::Collection;
+use serde_derive::{Deserialize, Serialize};
+use tracing::warn;
+use walkdir::WalkDir;
+
+use super::offer::canonicalized_path_to_string;
+
+// Name suffix of the metadata entry
+const META_SUFFIX: &str = ".sendme-meta";
+const META_VERSION: u32 = 1;
+// Read , write and execute for owner , group and others
+const MODE_MASK: u32 = 0o777;
+
+/// Metadata for all the files in a collection.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CollectionMeta {
+    version: u32,
+    files: BTreeMap<String, FileMeta>,
+    empty_dirs: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct FileMeta {
+    // unix mode bits
+    mode: Option<u32>,
+    // modification time since the unix epoch
+    mtime_secs: Option<u64>,
+    mtime_nanos: Option<u32>,
+}
+
+/// Index of the metadata entry , the last one when it has the name this
+/// sender gives it.
+pub fn meta_index(collection: &Collection) -> Option<usize> {
+    let last = collection.len().checked_sub(1)?;
+    if last == 0 {
+        return None;
+    }
+    let (name, _) = collection.iter().last()?;
+    (*name == meta_name(collection)).then_some(last)
+}
+
+// Hidden file next to the shared root
+fn meta_name(collection: &Collection) -> String {
+    let root = collection
+        .iter()
+        .next()
+        .and_then(|(name, _)| name.split('/').next())
+        .unwrap_or("collection");
+    format!(".{}{}", root, META_SUFFIX)
+}
+
+impl CollectionMeta {
+    /// Read the metadata of the shared files and any empty folders below `paths`.
+    pub fn collect(paths: &[PathBuf], data_sources: &[(String, PathBuf)]) -> Result<Self> {
+        let mut meta = CollectionMeta {
+            version: META_VERSION,
+            ..Default::default()
+        };
+        for (name, file) in data_sources {
+            let Ok(file_meta) = std::fs::metadata(file) else {
+                continue;
+            };
+            let mtime = file_meta
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+            meta.files.insert(
+                name.clone(),
+                FileMeta {
+                    mode: file_mode(&file_meta),
+                    mtime_secs: mtime.map(|mtime| mtime.as_secs()),
+                    mtime_nanos: mtime.map(|mtime| mtime.subsec_nanos()),
+                },
+            );
+        }
+        for path in paths {
+            let path = path.canonicalize()?;
+            let root = path.parent().context("context get parent")?;
+            for entry in WalkDir::new(&path)
+                .into_iter()
+                .filter_map(|entry| entry.ok())
+            {
+                if !entry.file_type().is_dir() {
+                    continue;
+                }
+                if std::fs::read_dir(entry.path())?.next().is_none() {
+                    let relative = entry.path().strip_prefix(root)?;
+                    meta.empty_dirs
+                        .push(canonicalized_path_to_string(relative, true)?);
+                }
+            }
+        }
+        Ok(meta)
+    }
+
+    /// Add the metadata to the collection as an extra entry.
+    ///
+    /// The returned tag protects the metadata blob until the collection is stored.
+    pub async fn add_to(&self, collection: &mut Collection, store: &Store) -> Result<TempTag> {
+        let data = serde_json::to_vec(self)?;
+        let tag = store.add_bytes(data).temp_tag().await?;
+        collection.push(meta_name(collection), *tag.hash());
+        Ok(tag)
+    }
+
+    /// Load the metadata entry of a collection, if it has one.
+    pub async fn load(collection: &Collection, store: &Store) -> Option<Self> {
+        let (_, hash) = collection.iter().nth(meta_index(collection)?)?;
+        let data = match store.get_bytes(*hash).await {
+            Ok(data) => data,
+            Err(err) => {
+                warn!("could not read metadata {err}");
+                return None;
+            }
+        };
+        match serde_json::from_slice::<CollectionMeta>(&data) {
+            Ok(meta) if meta.version <= META_VERSION => Some(meta),
+            Ok(meta) => {
+                warn!("unknown metadata version {}", meta.version);
+                None
+            }
+            Err(err) => {
+                warn!("bad metadata {err}");
+                None
+            }
+        }
+    }
+
+    /// Set the modes and times on the exported files and make the empty folders.
+    pub fn apply(&self, target_dir: &Path) -> Result<()> {
+        for dir in self.empty_dirs.iter() {
+            std::fs::create_dir_all(super::fetch::get_export_path(target_dir, dir)?)?;
+        }
+        for (name, file_meta) in self.files.iter() {
+            let target = super::fetch::get_export_path(target_dir, name)?;
+            if !target.exists() {
+                continue;
+            }
+            if let (Some(secs), Some(nanos)) = (file_meta.mtime_secs, file_meta.mtime_nanos) {
+                let mtime = UNIX_EPOCH + Duration::new(secs, nanos);
+                set_modified(&target, mtime)?;
+            }
+            if let Some(mode) = file_meta.mode {
+                set_mode(&target, mode)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn set_modified(path: &Path, mtime: SystemTime) -> Result<()> {
+    let file = std::fs::File::options().write(true).open(path)?;
+    file.set_modified(mtime)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn file_mode(meta: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(meta.permissions().mode() & MODE_MASK)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_meta: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & MODE_MASK))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_blobs::Hash;
+
+    use super::*;
+
+    fn collection(names: &[&str]) -> Collection {
+        names
+            .iter()
+            .map(|name| (name.to_string(), Hash::new(name.as_bytes())))
+            .collect()
+    }
+
+    #[test]
+    fn meta_entry_is_the_last_with_the_root_name() {
+        let shared = collection(&["docs/a.txt", "docs/b.txt", ".docs.sendme-meta"]);
+        assert_eq!(meta_index(&shared), Some(2));
+        // A file of the user that looks like metadata
+        let user = collection(&[".notes.sendme-meta", "docs/a.txt"]);
+        assert_eq!(meta_index(&user), None);
+        let other_root = collection(&["docs/a.txt", ".other.sendme-meta"]);
+        assert_eq!(meta_index(&other_root), None);
+        let alone = collection(&[".docs.sendme-meta"]);
+        assert_eq!(meta_index(&alone), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn apply_drops_special_mode_bits() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("sendme-meta-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("run.sh"), b"#!/bin/sh")?;
+        let mut meta = CollectionMeta::default();
+        meta.files.insert(
+            "run.sh".to_string(),
+            FileMeta {
+                mode: Some(0o6755),
+                ..Default::default()
+            },
+        );
+        meta.apply(&dir)?;
+        let mode = std::fs::metadata(dir.join("run.sh"))?.permissions().mode();
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(mode & 0o7777, 0o755);
+        Ok(())
+    }
+}
@@ -4,13 +4,28 @@
 // and construct endpoints at the top level
 
 // use anyhow::Result;
-use rand;
-
 use iroh::SecretKey;
 
+mod bundle;
+mod contacts;
+mod diag;
+mod dryrun;
 mod fetch;
+mod gc;
+mod inbox;
+mod limit;
+mod meta;
+mod nearby;
+mod net;
 mod offer;
-
+mod provider;
+mod replicate;
+mod seed;
+mod store;
+mod tags;
+mod ticket;
+mod verify;
+mod watch;
 
 /// Get the secret key or generate a new one.
 ///
@@ -31,5 +46,23 @@ pub fn get_or_create_secret() -> anyhow::Result<SecretKey> {
     // }
 }
 
-pub use fetch::receive;
-pub use offer::send;
+pub use bundle::{BUNDLE_EXTENSION, export_bundle, import_bundle};
+pub use contacts::{Contacts, ticket_node};
+pub use diag::{ConnectionDiag, PathKind};
+pub use dryrun::{DryRun, dry_run};
+pub use fetch::{FetchOptions, Preview, export_stored, preview, receive};
+pub use gc::{Collector, GcOptions, GcReport, collect_garbage, pin_tag, unpin_tag};
+pub use inbox::{Inbox, IncomingOffer, Offer, push_offer};
+pub use limit::Limits;
+pub use nearby::NearbyPeer;
+pub use net::{NetOptions, RelayModeOption, lenient_relay};
+pub use offer::{ImportChoice, SendOptions, Share, send, serve};
+pub use replicate::{Peers, ReplicaState, ReplicaStatus, push_replica};
+pub use seed::{SeedStats, Seeder, seed_tag, seeded, unseed_tag};
+pub use store::{copy_store, count_tags, memory_store, open_store};
+pub use tags::{
+    OUTGOING, TagEntry, TreeItem, collection_tree, delete_tag, list_tags, rename_tag, set_note,
+};
+pub use ticket::{AddrInfoOptions, TICKET_EXTENSION, TicketFile, is_ticket};
+pub use verify::{VerifyReport, drop_blob, repair_blob, verify_store};
+pub use watch::{ShareVersion, watch_share};
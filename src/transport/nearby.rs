@@ -0,0 +1,62 @@
+// Nearby devices
+// Nodes on the same network found over mdns , no internet needed.
+//
+// Every endpoint announces itself when local discovery is on so id only
+// tickets resolve on a LAN. Only the seeding node carries the device
+// name , unnamed nodes are passing shares and fetches and are left out
+// of the list.
+
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+use iroh::discovery::mdns;
+use iroh::{Endpoint, NodeId};
+use n0_future::StreamExt;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::comms::MessageOut;
+
+/// A named node seen on the local network.
+#[derive(Debug, Clone)]
+pub struct NearbyPeer {
+    pub node_id: NodeId,
+    pub name: String,
+    pub seen: SystemTime,
+}
+
+// Follow the discovery of `endpoint` and send the list on every change
+pub(super) fn watch_nearby(endpoint: &Endpoint, mess: MessageOut) -> JoinHandle<()> {
+    let mut stream = endpoint.discovery_stream();
+    tokio::spawn(async move {
+        let mut peers = BTreeMap::<NodeId, NearbyPeer>::new();
+        while let Some(item) = stream.next().await {
+            let item = match item {
+                Ok(item) => item,
+                // Missed some , the next announce brings them back
+                Err(err) => {
+                    warn!("nearby discovery {err}");
+                    continue;
+                }
+            };
+            if item.provenance() != mdns::NAME {
+                continue;
+            }
+            let Some(name) = item.user_data() else {
+                continue;
+            };
+            info!("nearby {} as {}", item.node_id(), name);
+            peers.insert(
+                item.node_id(),
+                NearbyPeer {
+                    node_id: item.node_id(),
+                    name: name.to_string(),
+                    seen: SystemTime::now(),
+                },
+            );
+            if let Err(err) = mess.nearby(peers.values().cloned().collect()).await {
+                warn!("nearby list not sent {err}");
+            }
+        }
+    })
+}
@@ -0,0 +1,241 @@
+// Network settings for every endpoint the app makes.
+// RelayModeOption is lifted from sendme.
+
+use std::fmt::{Display, Formatter};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::str::FromStr;
+
+use anyhow::Result;
+
+use iroh::discovery::UserData;
+use iroh::discovery::mdns::MdnsDiscovery;
+use iroh::endpoint::Builder;
+use iroh::{Endpoint, RelayMode, RelayUrl};
+use serde_derive::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Available options for configuring relays.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum RelayModeOption {
+    /// Disables relays altogether.
+    Disabled,
+    /// Uses the default relay servers.
+    #[default]
+    Default,
+    /// Uses a single, custom relay server by URL.
+    Custom(RelayUrl),
+}
+
+impl FromStr for RelayModeOption {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "disabled" => Ok(Self::Disabled),
+            "default" => Ok(Self::Default),
+            _ => Ok(Self::Custom(RelayUrl::from_str(s)?)),
+        }
+    }
+}
+
+impl Display for RelayModeOption {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Disabled => f.write_str("disabled"),
+            Self::Default => f.write_str("default"),
+            Self::Custom(url) => url.fmt(f),
+        }
+    }
+}
+
+impl From<RelayModeOption> for RelayMode {
+    fn from(value: RelayModeOption) -> Self {
+        match value {
+            RelayModeOption::Disabled => RelayMode::Disabled,
+            RelayModeOption::Default => RelayMode::Default,
+            RelayModeOption::Custom(url) => RelayMode::Custom(url.into()),
+        }
+    }
+}
+
+// Saved in the config as the same text the option is typed as
+impl TryFrom<String> for RelayModeOption {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<RelayModeOption> for String {
+    fn from(value: RelayModeOption) -> Self {
+        value.to_string()
+    }
+}
+
+/// Read a saved relay option , a bad one falls back to the default.
+///
+/// Keeps a typo in the relay url from failing the whole config.
+pub fn lenient_relay<'de, D>(deserializer: D) -> Result<RelayModeOption, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = <String as serde::Deserialize>::deserialize(deserializer)?;
+    Ok(value.parse().unwrap_or_else(|err| {
+        warn!("relay {value:?} in the config is not valid , using the default : {err}");
+        RelayModeOption::Default
+    }))
+}
+
+/// Network settings from the config.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetOptions {
+    pub relay: RelayModeOption,
+    /// Local addresses , port 0 picks a random port.
+    pub bind_v4: SocketAddrV4,
+    pub bind_v6: SocketAddrV6,
+    /// Find and announce nodes on the local network over mdns.
+    pub local_discovery: bool,
+    /// Name the seeding node announces , empty keeps it off nearby lists.
+    pub device_name: String,
+}
+
+impl Default for NetOptions {
+    fn default() -> Self {
+        Self {
+            relay: RelayModeOption::Default,
+            bind_v4: SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0),
+            bind_v6: SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 0, 0, 0),
+            local_discovery: false,
+            device_name: String::new(),
+        }
+    }
+}
+
+impl NetOptions {
+    // Builder for endpoints that others connect to , the fixed ports apply
+    pub(super) fn server_builder(&self) -> Builder {
+        let builder = Endpoint::builder()
+            .relay_mode(self.relay.clone().into())
+            .bind_addr_v4(self.bind_v4)
+            .bind_addr_v6(self.bind_v6);
+        self.local(builder)
+    }
+
+    // Builder for outgoing connections , the same addresses on random ports
+    // so a fetch does not fight a running share for the port
+    pub(super) fn client_builder(&self) -> Builder {
+        self.random_ports().server_builder()
+    }
+
+    /// The same settings on random ports , for an endpoint that must not
+    /// take the fixed ones from another.
+    pub fn random_ports(&self) -> Self {
+        let mut net = self.clone();
+        net.bind_v4.set_port(0);
+        net.bind_v6.set_port(0);
+        net
+    }
+
+    // Ids resolve on the LAN without the n0 dns
+    fn local(&self, builder: Builder) -> Builder {
+        if self.local_discovery {
+            builder.add_discovery(MdnsDiscovery::builder())
+        } else {
+            builder
+        }
+    }
+
+    /// The device name as discovery user data , none when it is empty or too long.
+    pub(super) fn user_data(&self) -> Option<UserData> {
+        let name = self.device_name.trim();
+        if name.is_empty() {
+            return None;
+        }
+        name.parse().ok()
+    }
+
+    /// Bind a serving endpoint and make sure it got the fixed ports.
+    ///
+    /// iroh quietly picks a random port when the one asked for is taken.
+    pub(super) async fn bind_server(&self, builder: Builder) -> Result<Endpoint> {
+        let endpoint = builder.bind().await?;
+        let bound = endpoint.bound_sockets();
+        for (port, v4) in [(self.bind_v4.port(), true), (self.bind_v6.port(), false)] {
+            let sockets = bound
+                .iter()
+                .filter(|addr| addr.is_ipv4() == v4)
+                .collect::<Vec<&SocketAddr>>();
+            // No ipv6 on this host is not an error
+            if port == 0 || sockets.is_empty() || sockets.iter().any(|addr| addr.port() == port) {
+                continue;
+            }
+            endpoint.close().await;
+            anyhow::bail!(
+                "UDP port {} is already in use , is another sendme serving ? Change it in the config.",
+                port
+            );
+        }
+        Ok(endpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Saved {
+        #[serde(deserialize_with = "lenient_relay")]
+        relay: RelayModeOption,
+    }
+
+    #[test]
+    fn relay_option_roundtrip() {
+        let options = [
+            RelayModeOption::Disabled,
+            RelayModeOption::Default,
+            RelayModeOption::Custom("https://relay.example.com".parse().unwrap()),
+        ];
+        for option in options {
+            let text = option.to_string();
+            assert_eq!(text.parse::<RelayModeOption>().unwrap(), option);
+            let saved = serde_json::to_string(&option).unwrap();
+            assert_eq!(
+                serde_json::from_str::<RelayModeOption>(&saved).unwrap(),
+                option
+            );
+        }
+        assert!("not a url".parse::<RelayModeOption>().is_err());
+    }
+
+    #[test]
+    fn bad_relay_falls_back_to_default() {
+        let saved: Saved = serde_json::from_str(r#"{"relay":"not a url"}"#).unwrap();
+        assert_eq!(saved.relay, RelayModeOption::Default);
+        let saved: Saved = serde_json::from_str(r#"{"relay":"disabled"}"#).unwrap();
+        assert_eq!(saved.relay, RelayModeOption::Disabled);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn random_ports_leave_the_fixed_port_alone() {
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let net = NetOptions {
+            relay: RelayModeOption::Disabled,
+            bind_v4: SocketAddrV4::new(Ipv4Addr::LOCALHOST, port),
+            ..Default::default()
+        };
+        let seeder = net.bind_server(net.server_builder()).await.unwrap();
+        assert!(net.bind_server(net.server_builder()).await.is_err());
+        let random = net.random_ports();
+        let share = random.bind_server(random.server_builder()).await.unwrap();
+        assert!(share.bound_sockets().iter().all(|addr| addr.port() != port));
+        share.close().await;
+        seeder.close().await;
+    }
+}
@@ -3,70 +3,351 @@
 // This is a cut and paste from sendme bits that have been updated
 // to use message and progress bars
 
+use super::diag::ConnectionWatch;
+use super::gc::Busy;
+use super::limit::{Limits, Throttle};
+use super::meta::CollectionMeta;
+use super::net::NetOptions;
+use super::provider;
+use super::seed::Served;
+use super::tags::{OUTGOING, new_tag, root_name};
+use super::ticket::{AddrInfoOptions, apply_options};
+use super::verify::record_source;
 use crate::comms::MessageOut;
 use anyhow::Context;
 use anyhow::Result;
-use anyhow::anyhow;
-use chrono::Local;
 use futures_buffered::BufferedStreamExt;
+use humansize::{DECIMAL, format_size};
 use iroh::Endpoint;
-use iroh::RelayMode;
+use iroh::SecretKey;
 use iroh::Watcher;
 use iroh::discovery::dns::DnsDiscovery;
+use iroh::discovery::pkarr::PkarrPublisher;
+use iroh::endpoint::Connection;
+use iroh::protocol::{AcceptError, ProtocolHandler, Router, RouterBuilder};
 use iroh_blobs::BlobFormat;
-use iroh_blobs::BlobsProtocol;
+use iroh_blobs::Hash;
+use iroh_blobs::api::Store;
 use iroh_blobs::api::TempTag;
 use iroh_blobs::api::blobs::AddPathOptions;
 use iroh_blobs::api::blobs::AddProgressItem;
 use iroh_blobs::api::blobs::ImportMode;
 use iroh_blobs::format::collection::Collection;
-use iroh_blobs::store::fs::FsStore;
+use iroh_blobs::provider::Event;
 use iroh_blobs::ticket::BlobTicket;
 use n0_future::StreamExt;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt::Display;
 use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
 // use tracing::info;
 use walkdir::WalkDir;
 
-// Not a mock anymore , breakdown.
-// TODO , cancellation feed to stop.
+// How often referenced files are checked for changes while serving
+const REFERENCE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How files are brought into the blob store when sending.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ImportChoice {
+    /// Copy files under the size threshold, reference the larger ones.
+    #[default]
+    Auto,
+    /// Always copy the file into the store (safe, uses extra disk).
+    Copy,
+    /// Reference the file in place (fast, the file must not change).
+    Reference,
+}
+
+impl ImportChoice {
+    pub const ALL: [ImportChoice; 3] = [Self::Auto, Self::Copy, Self::Reference];
+
+    // Pick the store import mode for a single file
+    fn mode_for(&self, size: u64, copy_threshold: u64) -> ImportMode {
+        match self {
+            Self::Copy => ImportMode::Copy,
+            Self::Reference => ImportMode::TryReference,
+            Self::Auto if size < copy_threshold => ImportMode::Copy,
+            Self::Auto => ImportMode::TryReference,
+        }
+    }
+}
+
+impl Display for ImportChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let val = match self {
+            Self::Auto => "Auto",
+            Self::Copy => "Copy",
+            Self::Reference => "Reference",
+        };
+        write!(f, "{}", val)
+    }
+}
+
+/// Per send settings chosen in the gui.
+#[derive(Debug, Clone)]
+pub struct SendOptions {
+    pub import: ImportChoice,
+    /// Files smaller than this (bytes) are copied in `Auto` mode.
+    pub copy_threshold: u64,
+    /// Send file modes, times and empty folders along with the files.
+    pub preserve_metadata: bool,
+    /// What address information goes into the ticket.
+    pub ticket_type: AddrInfoOptions,
+    /// The store is in memory , copy everything and leave no tags behind.
+    pub ephemeral: bool,
+    /// Upload limit of this share in bytes per second , zero is none.
+    pub upload_limit: u64,
+}
+
+// Size and modification time of a file that is served by reference.
+pub(super) struct FileStamp {
+    name: String,
+    path: PathBuf,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    pub(super) fn new(name: String, path: PathBuf) -> Result<Self> {
+        let meta = std::fs::metadata(&path)?;
+        Ok(Self {
+            name,
+            path,
+            size: meta.len(),
+            modified: meta.modified().ok(),
+        })
+    }
+
+    // Same size and time as another stamp of the file
+    pub(super) fn same(&self, other: &FileStamp) -> bool {
+        self.size == other.size && self.modified == other.modified
+    }
+
+    // Has the file changed since it was imported
+    fn changed(&self) -> bool {
+        match std::fs::metadata(&self.path) {
+            Ok(meta) => meta.len() != self.size || meta.modified().ok() != self.modified,
+            Err(_) => true,
+        }
+    }
+}
+
+/// A collection in the store that is ready to be served.
+pub struct Share {
+    pub hash: Hash,
+    ticket_type: AddrInfoOptions,
+    stamps: Vec<FileStamp>,
+    ephemeral: bool,
+}
 
-pub async fn send(path: PathBuf, mess: MessageOut, store: FsStore) -> Result<()> {
+impl Share {
+    /// Serve a collection that is already in the store.
+    pub fn existing(hash: Hash, ticket_type: AddrInfoOptions) -> Self {
+        Self {
+            hash,
+            ticket_type,
+            stamps: Vec::new(),
+            ephemeral: false,
+        }
+    }
+}
+
+// Not a mock anymore , breakdown.
+// Import the files and tag the collection, serving is separate.
+pub async fn send(
+    paths: Vec<PathBuf>,
+    options: SendOptions,
+    mess: MessageOut,
+    store: Store,
+) -> Result<Share> {
+    // Nothing can be referenced from memory
+    let options = if options.ephemeral {
+        SendOptions {
+            import: ImportChoice::Copy,
+            ..options
+        }
+    } else {
+        options
+    };
     // Import the files into the blob store
-    let (tag, size, _collection) = import(path, &store, mess.clone()).await?;
-    // Set a tag for later work
-    let dt = Local::now().to_rfc3339().to_owned();
-    store
-        .tags()
-        .set(format!("outgoing-{}", dt), tag.hash().to_owned())
+    let (tag, size, collection, stamps) = import(paths, &options, &store, mess.clone()).await?;
+    mess.info(format!("Imported {}", format_size(size, DECIMAL)).as_str())
         .await?;
+    if !stamps.is_empty() {
+        mess.info(format!("{} file(s) served by reference", stamps.len()).as_str())
+            .await?;
+    }
+    // Set a tag for later work
+    if !options.ephemeral {
+        let name = new_tag(&store, OUTGOING, &root_name(&collection), *tag.hash()).await?;
+        mess.info(format!("Tagged as {}", name).as_str()).await?;
+    }
+    Ok(Share {
+        hash: *tag.hash(),
+        ticket_type: options.ticket_type,
+        stamps,
+        ephemeral: options.ephemeral,
+    })
+}
+
+/// Serve a collection on a fresh endpoint until told to stop.
+///
+/// Every call makes a new node id and so a new ticket.
+pub async fn serve(
+    share: Share,
+    net: NetOptions,
+    limits: Limits,
+    mess: MessageOut,
+    store: Store,
+    stop: oneshot::Receiver<()>,
+) -> Result<()> {
+    let router = start_router(&store, &mess, share.ticket_type, &net, &limits).await?;
+    // Keep the gc off the collection while it is served
+    let busy = match share.ephemeral {
+        true => None,
+        false => Some(Busy::protect(&store, "share", share.hash).await?),
+    };
 
-    // Create the endpoint.
+    // Create the ticket
+    let mut addr = router.endpoint().node_addr().initialized().await;
+    apply_options(&mut addr, share.ticket_type);
+    let ticket = BlobTicket::new(addr, share.hash, BlobFormat::HashSeq);
+    mess.send_ticket(ticket.to_string()).await?;
+
+    // Serve until stopped or a referenced file changes
+    let res = tokio::select! {
+        _ = stop => Ok(()),
+        res = watch_references(&share.stamps, mess.clone()) => res,
+    };
+    router.shutdown().await?;
+    if let Some(busy) = busy {
+        busy.release().await?;
+    }
+    mess.info("Stopped serving").await?;
+    res
+}
+
+// Create the endpoint and attach the blob service
+pub(super) async fn start_router(
+    store: &Store,
+    mess: &MessageOut,
+    ticket_type: AddrInfoOptions,
+    net: &NetOptions,
+    limits: &Limits,
+) -> Result<Router> {
     // TODO move this up into the worker as an Option , create on demand
     let secret_key = super::get_or_create_secret()?;
-    let builder = Endpoint::builder()
+    let endpoint = bind_endpoint(mess, ticket_type, secret_key, net).await?;
+    Ok(blobs_router(store, endpoint, None, None, limits.serving(), mess, "send").spawn())
+}
+
+// Endpoint for serving , found through the n0 dns
+pub(super) async fn bind_endpoint(
+    mess: &MessageOut,
+    ticket_type: AddrInfoOptions,
+    secret_key: SecretKey,
+    net: &NetOptions,
+) -> Result<Endpoint> {
+    let mut builder = net
+        .server_builder()
         .alpns(vec![iroh_blobs::protocol::ALPN.to_vec()])
         .secret_key(secret_key)
-        .relay_mode(RelayMode::Default)
         .add_discovery(DnsDiscovery::n0_dns());
-    let endpoint = builder.bind().await?;
+    // Id only tickets need the address published to be found
+    if ticket_type == AddrInfoOptions::Id {
+        builder = builder.add_discovery(PkarrPublisher::n0_dns());
+    }
+    let endpoint = net.bind_server(builder).await?;
     mess.info("Local endpoint created...").await?;
+    Ok(endpoint)
+}
 
-    // Attach the services
-    let blobs = BlobsProtocol::new(&store, endpoint.clone(), None);
-    let router = iroh::protocol::Router::builder(endpoint)
-        .accept(iroh_blobs::ALPN, blobs.clone())
-        .spawn();
+// Attach the blob service , provider events go to `events` , only what
+// `served` allows is handed out when given , uploads are paced by
+// `throttle` and every connection is watched under `role` for the
+// diagnostics
+pub(super) fn blobs_router(
+    store: &Store,
+    endpoint: Endpoint,
+    events: Option<mpsc::Sender<Event>>,
+    served: Option<Served>,
+    throttle: Throttle,
+    mess: &MessageOut,
+    role: &'static str,
+) -> RouterBuilder {
+    let blobs = SharedBlobs {
+        store: store.clone(),
+        events,
+        served,
+        throttle,
+        endpoint: endpoint.clone(),
+        mess: mess.clone(),
+        role,
+    };
+    Router::builder(endpoint).accept(iroh_blobs::ALPN, blobs)
+}
 
-    // Create the ticket
-    let addr = router.endpoint().node_addr().initialized().await;
-    let ticket = BlobTicket::new(addr, tag.hash().to_owned(), BlobFormat::HashSeq);
-    mess.send_ticket(ticket.to_string()).await?;
+// The paced blob service. It has no shutdown hook , the one of
+// BlobsProtocol would also shut down the store the worker keeps using
+// after the router is gone.
+#[derive(Clone)]
+struct SharedBlobs {
+    store: Store,
+    events: Option<mpsc::Sender<Event>>,
+    served: Option<Served>,
+    throttle: Throttle,
+    endpoint: Endpoint,
+    mess: MessageOut,
+    role: &'static str,
+}
+
+impl std::fmt::Debug for SharedBlobs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedBlobs")
+            .field("role", &self.role)
+            .finish()
+    }
+}
 
-    // TODO , wait and serve , wait for the cancel.
-    tokio::signal::ctrl_c().await?;
-    Err(anyhow!("Send Fail"))
+impl ProtocolHandler for SharedBlobs {
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let node_id = connection.remote_node_id()?;
+        let watch = ConnectionWatch::start(&self.endpoint, node_id, self.role, self.mess.clone());
+        provider::handle_connection(
+            connection,
+            self.store.clone(),
+            self.events.clone(),
+            self.served.clone(),
+            self.throttle.clone(),
+        )
+        .await;
+        watch.finish().await;
+        Ok(())
+    }
+}
+
+// Referenced files are not owned by the store, if one is edited while it is
+// being served the store goes bad. Check them and stop serving on change.
+async fn watch_references(stamps: &[FileStamp], mess: MessageOut) -> Result<()> {
+    if stamps.is_empty() {
+        return std::future::pending().await;
+    }
+    let mut interval = tokio::time::interval(REFERENCE_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Some(stamp) = stamps.iter().find(|stamp| stamp.changed()) {
+            mess.error(
+                format!("{} was modified while shared, stopped serving", stamp.name).as_str(),
+            )
+            .await?;
+            return Ok(());
+        }
+    }
 }
 
 /// Import from a file or directory into the database.
@@ -76,20 +357,68 @@ pub async fn send(path: PathBuf, mess: MessageOut, store: FsStore) -> Result<()>
 ///
 /// If the input is a directory, the collection contains all the files in the
 /// directory.
+///
+/// Several inputs go into the one collection, their names must not clash.
+///
+/// Files that were imported by reference are returned with their size and
+/// modification time so they can be watched while serving.
 async fn import(
-    path: PathBuf,
-    store: &FsStore,
+    paths: Vec<PathBuf>,
+    options: &SendOptions,
+    store: &Store,
     mess: MessageOut,
-) -> anyhow::Result<(TempTag, u64, Collection)> {
-    let parallelism = num_cpus::get();
+) -> anyhow::Result<(TempTag, u64, Collection, Vec<FileStamp>)> {
+    let mut data_sources = Vec::new();
+    for path in paths.iter() {
+        data_sources.extend(self::data_sources(path)?);
+    }
+    data_sources.sort_by(|(a, _), (b, _)| a.cmp(b));
+    if let Some(pair) = data_sources.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        anyhow::bail!("{} is in the share twice", pair[0].0);
+    }
+    let meta = options
+        .preserve_metadata
+        .then(|| CollectionMeta::collect(&paths, &data_sources))
+        .transpose()?;
+    let mut names_and_tags = import_files(data_sources, options, store, mess).await?;
+    // op.finish_and_clear();
+    names_and_tags.sort_by(|(a, _, _, _), (b, _, _, _)| a.cmp(b));
+    // total size of all files
+    let size = names_and_tags
+        .iter()
+        .map(|(_, _, size, _)| *size)
+        .sum::<u64>();
+    // keep the referenced files for watching
+    let stamps = names_and_tags
+        .iter_mut()
+        .filter_map(|(_, _, _, stamp)| stamp.take())
+        .collect::<Vec<_>>();
+    // collect the (name, hash) tuples into a collection
+    // we must also keep the tags around so the data does not get gced.
+    let (mut collection, mut tags) = names_and_tags
+        .into_iter()
+        .map(|(name, tag, _, _)| ((name, *tag.hash()), tag))
+        .unzip::<_, _, Collection, Vec<_>>();
+    // file modes, times and empty folders go in as one extra entry
+    if let Some(meta) = meta {
+        tags.push(meta.add_to(&mut collection, store).await?);
+    }
+    let temp_tag = collection.clone().store(store).await?;
+    // now that the collection is stored, we can drop the tags
+    // data is protected by the collection
+    drop(tags);
+    Ok((temp_tag, size, collection, stamps))
+}
+
+// Flatten the directory structure into a list of (name, path) pairs.
+pub(super) fn data_sources(path: &Path) -> Result<Vec<(String, PathBuf)>> {
     let path = path.canonicalize()?;
     anyhow::ensure!(path.exists(), "path {} does not exist", path.display());
     let root = path.parent().context("context get parent")?;
     // walkdir also works for files, so we don't need to special case them
     let files = WalkDir::new(path.clone()).into_iter();
-    // flatten the directory structure into a list of (name, path) pairs.
     // ignore symlinks.
-    let data_sources: Vec<(String, PathBuf)> = files
+    files
         .map(|entry| {
             let entry = entry?;
             if !entry.file_type().is_file() {
@@ -101,20 +430,28 @@ async fn import(
             anyhow::Ok(Some((name, path)))
         })
         .filter_map(Result::transpose)
-        .collect::<anyhow::Result<Vec<_>>>()?;
-    // import all the files, using num_cpus workers, return names and temp tags
-    // let op = mp.add(make_import_overall_progress());
-    // op.set_message(format!("importing {} files", data_sources.len()));
-    // op.set_length(data_sources.len() as u64);
-    let mut names_and_tags = n0_future::stream::iter(data_sources)
+        .collect::<anyhow::Result<Vec<_>>>()
+}
+
+// Import all the files, using num_cpus workers, return names and temp tags
+pub(super) async fn import_files(
+    data_sources: Vec<(String, PathBuf)>,
+    options: &SendOptions,
+    store: &Store,
+    mess: MessageOut,
+) -> Result<Vec<(String, TempTag, u64, Option<FileStamp>)>> {
+    let parallelism = num_cpus::get();
+    n0_future::stream::iter(data_sources)
         .map(|(name, path)| {
             let db = store.clone();
             // This clones a mutex for each file , seems to work.
             let m = mess.clone();
             async move {
+                let stamp = FileStamp::new(name.clone(), path.clone())?;
+                let mode = options.import.mode_for(stamp.size, options.copy_threshold);
                 let import = db.add_path_with_opts(AddPathOptions {
                     path,
-                    mode: ImportMode::TryReference,
+                    mode,
                     format: BlobFormat::Raw,
                 });
                 let mut stream = import.stream().await;
@@ -149,29 +486,18 @@ async fn import(
                         }
                     }
                 };
-                anyhow::Ok((name, temp_tag, item_size))
+                if mode == ImportMode::TryReference {
+                    record_source(&db, *temp_tag.hash(), &stamp.path).await?;
+                }
+                let stamp = (mode == ImportMode::TryReference).then_some(stamp);
+                anyhow::Ok((name, temp_tag, item_size, stamp))
             }
         })
         .buffered_unordered(parallelism)
         .collect::<Vec<_>>()
         .await
         .into_iter()
-        .collect::<anyhow::Result<Vec<_>>>()?;
-    // op.finish_and_clear();
-    names_and_tags.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
-    // total size of all files
-    let size = names_and_tags.iter().map(|(_, _, size)| *size).sum::<u64>();
-    // collect the (name, hash) tuples into a collection
-    // we must also keep the tags around so the data does not get gced.
-    let (collection, tags) = names_and_tags
-        .into_iter()
-        .map(|(name, tag, _)| ((name, *tag.hash()), tag))
-        .unzip::<_, _, Collection, Vec<_>>();
-    let temp_tag = collection.clone().store(store).await?;
-    // now that the collection is stored, we can drop the tags
-    // data is protected by the collection
-    drop(tags);
-    Ok((temp_tag, size, collection))
+        .collect::<anyhow::Result<Vec<_>>>()
 }
 
 /// From original sendme.
@@ -0,0 +1,332 @@
+// Provider
+// The blobs provider of every router , paced and on the seeding node held
+// to what `Served` allows.
+//
+// iroh-blobs 0.93 has no hook to pace a transfer or to turn down a request
+// by hash , and the writer its handlers take can not be built outside the
+// crate. Pacing below it in the congestion controller can not hold a rate
+// once a round trip is shorter than a few packets take at that rate. So
+// get and get many are answered here the way iroh_blobs::provider does ,
+// with the same events , only every write goes through `PacedSend`.
+// Nothing here observes or takes pushes , those requests are refused.
+// Drop this for the stock provider once it takes a throttle.
+
+use anyhow::{Result, anyhow};
+use bytes::Bytes;
+use iroh::endpoint::{Connection, RecvStream, SendStream};
+use iroh_blobs::Hash;
+use iroh_blobs::api::Store;
+use iroh_blobs::api::blobs::EncodedItem;
+use iroh_blobs::hashseq::HashSeq;
+use iroh_blobs::protocol::{ChunkRanges, GetManyRequest, GetRequest, Request};
+use iroh_blobs::provider::{CountingReader, Event, EventSender, StreamContext};
+use n0_future::StreamExt;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use super::limit::Throttle;
+use super::seed::Served;
+
+// Follows iroh_blobs::provider::handle_connection
+pub(super) async fn handle_connection(
+    connection: Connection,
+    store: Store,
+    events: Option<mpsc::Sender<Event>>,
+    served: Option<Served>,
+    throttle: Throttle,
+) {
+    let connection_id = connection.stable_id() as u64;
+    let Ok(node_id) = connection.remote_node_id() else {
+        warn!("failed to get node id");
+        return;
+    };
+    let progress = EventSender::new(events.clone());
+    if !progress
+        .authorize_client_connection(connection_id, node_id)
+        .await
+    {
+        debug!("client not authorized to connect");
+        return;
+    }
+    while let Ok((send, recv)) = connection.accept_bi().await {
+        let mut context = StreamContext {
+            connection_id,
+            request_id: recv.id().index(),
+            payload_bytes_sent: 0,
+            other_bytes_sent: 0,
+            bytes_read: 0,
+            progress: progress.clone(),
+        };
+        let store = store.clone();
+        let served = served.clone();
+        let mut send = PacedSend {
+            send,
+            throttle: throttle.clone(),
+        };
+        tokio::spawn(async move {
+            match handle_stream(&store, &mut send, recv, &mut context, served).await {
+                Ok(()) => context.send_transfer_completed().await,
+                Err(err) => {
+                    warn!("provider stream failed {err:#}");
+                    context.send_transfer_aborted().await;
+                }
+            }
+        });
+    }
+    if let Some(events) = events {
+        events
+            .send(Event::ConnectionClosed { connection_id })
+            .await
+            .ok();
+    }
+}
+
+// A send stream that waits on the throttle before every write
+struct PacedSend {
+    send: SendStream,
+    throttle: Throttle,
+}
+
+impl PacedSend {
+    async fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        self.throttle.take(data.len() as u64).await;
+        self.send.write_all(data).await?;
+        Ok(())
+    }
+
+    async fn write_chunk(&mut self, data: Bytes) -> Result<()> {
+        self.throttle.take(data.len() as u64).await;
+        self.send.write_chunk(data).await?;
+        Ok(())
+    }
+}
+
+async fn handle_stream(
+    store: &Store,
+    send: &mut PacedSend,
+    mut recv: RecvStream,
+    context: &mut StreamContext,
+    served: Option<Served>,
+) -> Result<()> {
+    let mut reader = CountingReader::new(&mut recv);
+    let request = Request::read_async(&mut reader).await?;
+    context.bytes_read += reader.read();
+    match request {
+        Request::Get(request) => {
+            accept_request(store, &mut recv, served, &[request.hash]).await?;
+            handle_get(store, request, send, context).await?;
+        }
+        Request::GetMany(request) => {
+            accept_request(store, &mut recv, served, &request.hashes).await?;
+            handle_get_many(store, request, send, context).await?;
+        }
+        other => return Err(anyhow!("unsupported request {other:?}")),
+    }
+    send.send.finish()?;
+    Ok(())
+}
+
+// Nothing may follow the request , and the seeding node only answers for
+// what it serves
+async fn accept_request(
+    store: &Store,
+    recv: &mut RecvStream,
+    served: Option<Served>,
+    hashes: &[Hash],
+) -> Result<()> {
+    recv.read_to_end(0).await?;
+    if let Some(served) = served {
+        for hash in hashes {
+            if !served.allows(store, *hash).await? {
+                return Err(anyhow!("{} is not served", hash.fmt_short()));
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn handle_get(
+    store: &Store,
+    request: GetRequest,
+    send: &mut PacedSend,
+    context: &mut StreamContext,
+) -> Result<()> {
+    let hash = request.hash;
+    context
+        .send_get_request_received(&hash, &request.ranges)
+        .await;
+    let mut hash_seq = None;
+    for (offset, ranges) in request.ranges.iter_non_empty_infinite() {
+        let child = match offset {
+            0 => hash,
+            _ => {
+                if hash_seq.is_none() {
+                    hash_seq = Some(HashSeq::try_from(store.get_bytes(hash).await?)?);
+                }
+                let index = usize::try_from(offset - 1)?;
+                match hash_seq.as_ref().and_then(|seq| seq.get(index)) {
+                    Some(child) => child,
+                    None => break,
+                }
+            }
+        };
+        send_blob(store, offset, child, ranges.clone(), send, context).await?;
+    }
+    Ok(())
+}
+
+async fn handle_get_many(
+    store: &Store,
+    request: GetManyRequest,
+    send: &mut PacedSend,
+    context: &mut StreamContext,
+) -> Result<()> {
+    context
+        .send_get_many_request_received(&request.hashes, &request.ranges)
+        .await;
+    let ranges = request.ranges.iter_infinite();
+    for (index, (hash, ranges)) in request.hashes.iter().zip(ranges).enumerate() {
+        if !ranges.is_empty() {
+            send_blob(store, index as u64, *hash, ranges.clone(), send, context).await?;
+        }
+    }
+    Ok(())
+}
+
+// Write the verified stream of one blob and report the payload
+async fn send_blob(
+    store: &Store,
+    index: u64,
+    hash: Hash,
+    ranges: ChunkRanges,
+    send: &mut PacedSend,
+    context: &mut StreamContext,
+) -> Result<()> {
+    let mut items = store.export_bao(hash, ranges).stream();
+    while let Some(item) = items.next().await {
+        match item {
+            EncodedItem::Size(size) => {
+                context.send_transfer_started(index, &hash, size).await;
+                send.write_all(&size.to_le_bytes()).await?;
+                context.log_other_write(8);
+            }
+            EncodedItem::Parent(parent) => {
+                let mut data = [0u8; 64];
+                data[..32].copy_from_slice(parent.pair.0.as_bytes());
+                data[32..].copy_from_slice(parent.pair.1.as_bytes());
+                send.write_all(&data).await?;
+                context.log_other_write(64);
+            }
+            EncodedItem::Leaf(leaf) => {
+                let len = leaf.data.len();
+                send.write_chunk(leaf.data).await?;
+                context.notify_payload_write(index, leaf.offset, len);
+            }
+            EncodedItem::Done => break,
+            EncodedItem::Error(err) => return Err(anyhow!("export of {} failed , {}", hash, err)),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::protocol::Router;
+    use iroh::{Endpoint, RelayMode, Watcher};
+    use iroh_blobs::api::blobs::BlobStatus;
+    use iroh_blobs::protocol::{ChunkRangesSeq, PushRequest};
+
+    use super::*;
+    use crate::comms::MessageOut;
+    use crate::transport::Limits;
+    use crate::transport::memory_store;
+    use crate::transport::offer::blobs_router;
+
+    // A provider on a local endpoint and a connection to it
+    async fn connect(store: &Store) -> Result<(Router, Connection)> {
+        let (event_tx, _event_rx) = async_channel::unbounded();
+        let mess = MessageOut::new(event_tx);
+        let server = Endpoint::builder()
+            .relay_mode(RelayMode::Disabled)
+            .bind()
+            .await?;
+        let addr = server.node_addr().initialized().await;
+        let limits = Limits::default();
+        let router =
+            blobs_router(store, server, None, None, limits.serving(), &mess, "send").spawn();
+        let client = Endpoint::builder()
+            .relay_mode(RelayMode::Disabled)
+            .bind()
+            .await?;
+        let connection = client.connect(addr, iroh_blobs::ALPN).await?;
+        Ok((router, connection))
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn get_sends_the_root_and_children() -> Result<()> {
+        let store = memory_store();
+        let first = store.add_bytes(b"first".to_vec()).await?.hash;
+        let second = store.add_bytes(vec![2u8; 100_000]).await?.hash;
+        let root = store
+            .add_bytes(HashSeq::from_iter([first, second]))
+            .await?
+            .hash;
+        let (router, connection) = connect(&store).await?;
+
+        let target = memory_store();
+        target
+            .remote()
+            .execute_get(connection, GetRequest::all(root))
+            .complete()
+            .await?;
+        assert_eq!(target.get_bytes(first).await?, b"first".as_slice());
+        assert_eq!(target.get_bytes(second).await?, vec![2u8; 100_000]);
+        router.shutdown().await?;
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn get_many_sends_each_hash() -> Result<()> {
+        let store = memory_store();
+        let first = store.add_bytes(b"first".to_vec()).await?.hash;
+        let second = store.add_bytes(b"second".to_vec()).await?.hash;
+        let (router, connection) = connect(&store).await?;
+
+        let target = memory_store();
+        let request = GetManyRequest::builder()
+            .hash(first, ChunkRanges::all())
+            .hash(second, ChunkRanges::all())
+            .build();
+        target
+            .remote()
+            .execute_get_many(connection, request)
+            .complete()
+            .await?;
+        assert_eq!(target.get_bytes(first).await?, b"first".as_slice());
+        assert_eq!(target.get_bytes(second).await?, b"second".as_slice());
+        router.shutdown().await?;
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn push_is_refused() -> Result<()> {
+        let store = memory_store();
+        let (router, connection) = connect(&store).await?;
+
+        let source = memory_store();
+        let hash = source.add_bytes(b"pushed".to_vec()).await?.hash;
+        let request = PushRequest::new(hash, ChunkRangesSeq::root());
+        // The stream is dropped , the push may or may not see an error
+        let _ = source
+            .remote()
+            .execute_push(connection, request)
+            .complete()
+            .await;
+        assert!(matches!(
+            store.blobs().status(hash).await?,
+            BlobStatus::NotFound
+        ));
+        router.shutdown().await?;
+        Ok(())
+    }
+}
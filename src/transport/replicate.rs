@@ -0,0 +1,200 @@
+// Replication
+// Collections are pushed to a set of peer nodes that keep a copy.
+//
+// The origin asks a peer over REPLICATE_ALPN to fetch a collection from
+// it. The peer pulls only the missing ranges over the blobs protocol,
+// tags the copy as a `replica` and answers when it is done. Requests are
+// only taken from the configured peers , both sides list each other.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Result, anyhow};
+use iroh::endpoint::{Connection, RecvStream};
+use iroh::protocol::{AcceptError, ProtocolHandler};
+use iroh::{Endpoint, NodeAddr, NodeId, Watcher};
+use iroh_blobs::api::Store;
+use iroh_blobs::api::remote::GetProgressItem;
+use iroh_blobs::format::collection::Collection;
+use iroh_blobs::{Hash, HashAndFormat};
+use n0_future::StreamExt;
+use serde_derive::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::gc::Busy;
+use super::limit::Throttle;
+use super::tags::{REPLICA, list_tags, new_tag, root_name};
+use crate::comms::MessageOut;
+
+/// Protocol for asking a peer to take a copy.
+pub const REPLICATE_ALPN: &[u8] = b"sendme-egui/replicate/0";
+// Requests and answers are small json messages
+const MAX_MESSAGE: usize = 64 * 1024;
+
+/// Nodes that replicas are pushed to and taken from.
+pub type Peers = Arc<Mutex<HashSet<NodeId>>>;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReplicaRequest {
+    hash: Hash,
+    // Where to fetch from , the peer may not find it by id alone
+    from: NodeAddr,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum ReplicaResponse {
+    Done { bytes: u64 },
+    Failed(String),
+}
+
+/// Where a push to one peer stands.
+#[derive(Debug, Clone)]
+pub enum ReplicaState {
+    Running,
+    /// Bytes the peer had to fetch , zero if it had everything
+    Done(u64),
+    Failed(String),
+}
+
+/// Replication of a tag to a peer.
+#[derive(Debug, Clone)]
+pub struct ReplicaStatus {
+    pub tag: String,
+    pub peer: NodeId,
+    pub state: ReplicaState,
+}
+
+/// Ask `peer` to take a copy of a collection , waits until it has one.
+pub async fn push_replica(endpoint: &Endpoint, peer: NodeId, hash: Hash) -> Result<u64> {
+    let from = endpoint.node_addr().initialized().await;
+    let connection = endpoint.connect(peer, REPLICATE_ALPN).await?;
+    let (mut send, mut recv) = connection.open_bi().await?;
+    let request = serde_json::to_vec(&ReplicaRequest { hash, from })?;
+    send.write_all(&request).await?;
+    send.finish()?;
+    let response = recv.read_to_end(MAX_MESSAGE).await?;
+    connection.close(0u32.into(), b"done");
+    match serde_json::from_slice(&response)? {
+        ReplicaResponse::Done { bytes } => Ok(bytes),
+        ReplicaResponse::Failed(err) => Err(anyhow!("{} : {}", peer.fmt_short(), err)),
+    }
+}
+
+// The peer side , fetches what it is asked for into the local store
+#[derive(Clone)]
+pub(super) struct ReplicaService {
+    pub(super) store: Store,
+    pub(super) endpoint: Endpoint,
+    pub(super) peers: Peers,
+    pub(super) throttle: Throttle,
+    pub(super) mess: MessageOut,
+}
+
+impl fmt::Debug for ReplicaService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplicaService")
+            .field("peers", &self.peers)
+            .finish()
+    }
+}
+
+impl ProtocolHandler for ReplicaService {
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let peer = connection.remote_node_id()?;
+        if !self.peers.lock().expect("peer lock").contains(&peer) {
+            warn!("replication request from unknown node {peer}");
+            return Err(AcceptError::NotAllowed {});
+        }
+        let (mut send, mut recv) = connection.accept_bi().await?;
+        let response = match self.take_replica(&mut recv, peer).await {
+            Ok(bytes) => ReplicaResponse::Done { bytes },
+            Err(err) => {
+                warn!("replica for {peer} failed {err}");
+                let _ = self
+                    .mess
+                    .error(format!("Replica for {} failed , {}", peer.fmt_short(), err).as_str())
+                    .await;
+                ReplicaResponse::Failed(err.to_string())
+            }
+        };
+        let response = serde_json::to_vec(&response).map_err(AcceptError::from_err)?;
+        send.write_all(&response)
+            .await
+            .map_err(AcceptError::from_err)?;
+        send.finish().map_err(AcceptError::from_err)?;
+        connection.closed().await;
+        Ok(())
+    }
+}
+
+impl ReplicaService {
+    async fn take_replica(&self, recv: &mut RecvStream, peer: NodeId) -> Result<u64> {
+        let request: ReplicaRequest =
+            serde_json::from_slice(&recv.read_to_end(MAX_MESSAGE).await?)?;
+        anyhow::ensure!(
+            request.from.node_id == peer,
+            "asked to fetch from another node"
+        );
+        self.mess
+            .info(
+                format!(
+                    "Replicating {} for {}",
+                    request.hash.fmt_short(),
+                    peer.fmt_short()
+                )
+                .as_str(),
+            )
+            .await?;
+        // Keep the gc off the partial copy until it is tagged
+        let busy = Busy::protect(&self.store, "replica", request.hash).await?;
+        let res = self.fetch(request).await;
+        busy.release().await?;
+        res
+    }
+
+    // Only the missing ranges go over the wire
+    async fn fetch(&self, request: ReplicaRequest) -> Result<u64> {
+        let hash = request.hash;
+        let local = self
+            .store
+            .remote()
+            .local(HashAndFormat::hash_seq(hash))
+            .await?;
+        let mut bytes = 0;
+        if !local.is_complete() {
+            let connection = self
+                .endpoint
+                .connect(request.from, iroh_blobs::ALPN)
+                .await?;
+            let mut progress = self
+                .store
+                .remote()
+                .execute_get(connection, local.missing())
+                .stream();
+            while let Some(item) = progress.next().await {
+                match item {
+                    GetProgressItem::Progress(offset) => {
+                        self.throttle.take(offset - bytes).await;
+                        bytes = offset;
+                    }
+                    GetProgressItem::Done(stats) => bytes = stats.payload_bytes_read,
+                    GetProgressItem::Error(err) => return Err(err.into()),
+                }
+            }
+        }
+        let tagged = list_tags(&self.store, REPLICA)
+            .await?
+            .iter()
+            .any(|entry| entry.hash == hash);
+        if !tagged {
+            let collection = Collection::load(hash, &self.store).await?;
+            let tag = new_tag(&self.store, REPLICA, &root_name(&collection), hash).await?;
+            self.mess
+                .correct(format!("Replica stored as {}", tag).as_str())
+                .await?;
+        }
+        info!("replica of {hash} done , {bytes} bytes fetched");
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,489 @@
+// Seeding
+// Tags marked with a `seed-` tag are served for as long as the app runs.
+//
+// The seeding node keeps its key in the store folder so tickets handed
+// out stay good across restarts, and it publishes itself to discovery
+// for when the addresses change. The provider events are counted per
+// seeded collection for the dashboard.
+//
+// The same node takes and sends replication requests and pushed offers ,
+// peers and contacts know it by the stable id. With local discovery on it
+// announces the device name and keeps the list of nearby devices.
+//
+// The node is found by anyone who knows its id , so it only hands out the
+// seeded collections , the replicas it keeps for peers and what it offered
+// or replicated to others. Other data in the store is refused.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use iroh::protocol::Router;
+use iroh::{Endpoint, NodeAddr, NodeId, SecretKey, Watcher};
+use iroh_blobs::api::Store;
+use iroh_blobs::hashseq::HashSeq;
+use iroh_blobs::provider::Event;
+use iroh_blobs::ticket::BlobTicket;
+use iroh_blobs::{BlobFormat, Hash, HashAndFormat};
+use n0_future::StreamExt;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use super::inbox::{INBOX_ALPN, Inbox, InboxService};
+use super::limit::Limits;
+use super::nearby::watch_nearby;
+use super::net::NetOptions;
+use super::offer::{bind_endpoint, blobs_router};
+use super::replicate::{Peers, REPLICATE_ALPN, ReplicaService};
+use super::tags::REPLICA;
+use super::ticket::AddrInfoOptions;
+use crate::comms::MessageOut;
+
+/// Prefix of the tags that mark a collection for seeding.
+pub const SEED_PREFIX: &str = "seed-";
+// File in the store folder with the key of the seeding node
+const KEY_FILE: &str = "seed.key";
+
+/// What a seeded collection has done since the app started.
+#[derive(Debug, Clone)]
+pub struct SeedStats {
+    pub tag: String,
+    pub hash: Hash,
+    pub ticket: String,
+    pub bytes: u64,
+    /// The nodes that fetched it
+    pub peers: Vec<NodeId>,
+    pub requests: usize,
+}
+
+// Counters of one seeded collection
+#[derive(Debug)]
+struct Seed {
+    hash: Hash,
+    bytes: u64,
+    peers: HashSet<NodeId>,
+    requests: usize,
+}
+
+type Seeds = Arc<Mutex<BTreeMap<String, Seed>>>;
+
+// A node connecting to or leaving the provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Visit {
+    Came(NodeId),
+    Left(NodeId),
+}
+
+/// The collections the seeding node hands out.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Served(Arc<Mutex<ServedRoots>>);
+
+#[derive(Debug, Default)]
+struct ServedRoots {
+    // Seed and replica tags , follows the store
+    tagged: HashSet<Hash>,
+    // Offered or replicated to another node , until the app stops
+    offered: HashSet<Hash>,
+}
+
+impl Served {
+    fn set_tagged(&self, roots: HashSet<Hash>) {
+        self.0.lock().expect("served lock").tagged = roots;
+    }
+
+    fn offer(&self, root: Hash) {
+        self.0.lock().expect("served lock").offered.insert(root);
+    }
+
+    /// Is `hash` one of the collections or a blob in one of them.
+    pub(super) async fn allows(&self, store: &Store, hash: Hash) -> Result<bool> {
+        let roots = {
+            let served = self.0.lock().expect("served lock");
+            if served.tagged.contains(&hash) || served.offered.contains(&hash) {
+                return Ok(true);
+            }
+            served
+                .tagged
+                .union(&served.offered)
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        for root in roots {
+            let Ok(bytes) = store.get_bytes(root).await else {
+                continue;
+            };
+            if HashSeq::try_from(bytes).is_ok_and(|seq| seq.iter().any(|child| child == hash)) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// The node serving the seeded collections.
+pub struct Seeder {
+    router: Router,
+    addr: NodeAddr,
+    seeds: Seeds,
+    events: JoinHandle<()>,
+    nearby: Option<JoinHandle<()>>,
+    served: Served,
+    visits: broadcast::Sender<Visit>,
+}
+
+impl Seeder {
+    /// Start the seeding node for the store at `store_path`.
+    ///
+    /// Replication requests are taken from `peers` , offers go to `inbox`.
+    pub async fn start(
+        store: &Store,
+        store_path: &Path,
+        peers: Peers,
+        inbox: Inbox,
+        net: &NetOptions,
+        limits: &Limits,
+        mess: MessageOut,
+    ) -> Result<Self> {
+        let secret_key = seed_key(store_path)?;
+        let (events_tx, events_rx) = mpsc::channel(64);
+        let endpoint = bind_endpoint(&mess, AddrInfoOptions::Id, secret_key, net).await?;
+        let replicas = ReplicaService {
+            store: store.clone(),
+            endpoint: endpoint.clone(),
+            peers,
+            throttle: limits.replicating(),
+            mess: mess.clone(),
+        };
+        let offers = InboxService {
+            inbox,
+            mess: mess.clone(),
+        };
+        let mut nearby = None;
+        if net.local_discovery {
+            endpoint.set_user_data_for_discovery(net.user_data());
+            nearby = Some(watch_nearby(&endpoint, mess.clone()));
+        }
+        let served = Served::default();
+        let router = blobs_router(
+            store,
+            endpoint,
+            Some(events_tx),
+            Some(served.clone()),
+            limits.seeding(),
+            &mess,
+            "seed",
+        )
+        .accept(REPLICATE_ALPN, replicas)
+        .accept(INBOX_ALPN, offers)
+        .spawn();
+        let addr = router.endpoint().node_addr().initialized().await;
+        info!("seeding as {}", addr.node_id);
+        let seeds = Seeds::default();
+        let (visits, _) = broadcast::channel(64);
+        let events = tokio::spawn(count_events(
+            events_rx,
+            seeds.clone(),
+            visits.clone(),
+            addr.clone(),
+            mess,
+        ));
+        let seeder = Self {
+            router,
+            addr,
+            seeds,
+            events,
+            nearby,
+            served,
+            visits,
+        };
+        seeder.update(store).await?;
+        Ok(seeder)
+    }
+
+    /// Pick up seeds that were added or removed , counters are kept.
+    pub async fn update(&self, store: &Store) -> Result<()> {
+        let current = seeded(store).await?;
+        let mut roots = current.values().copied().collect::<HashSet<_>>();
+        let mut replicas = store.tags().list_prefix(REPLICA).await?;
+        while let Some(info) = replicas.next().await {
+            roots.insert(info?.hash);
+        }
+        self.served.set_tagged(roots);
+        let mut seeds = self.seeds.lock().expect("seed lock");
+        seeds.retain(|tag, seed| current.get(tag) == Some(&seed.hash));
+        for (tag, hash) in current {
+            seeds.entry(tag).or_insert_with(|| Seed {
+                hash,
+                bytes: 0,
+                peers: HashSet::new(),
+                requests: 0,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn endpoint(&self) -> &Endpoint {
+        self.router.endpoint()
+    }
+
+    pub fn node_id(&self) -> NodeId {
+        self.addr.node_id
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seeds.lock().expect("seed lock").is_empty()
+    }
+
+    /// Hand out a collection that is offered or replicated to another node.
+    pub fn offer(&self, hash: Hash) {
+        self.served.offer(hash);
+    }
+
+    /// Resolves once `peer` has fetched from the node and gone , or did
+    /// not come within `wait` , or the node stopped.
+    ///
+    /// Only visits after the call count.
+    pub fn fetched_by(&self, peer: NodeId, wait: Duration) -> impl Future<Output = ()> + use<> {
+        visited(self.visits.subscribe(), peer, wait)
+    }
+
+    /// Counters and tickets for the dashboard.
+    pub fn stats(&self) -> Vec<SeedStats> {
+        snapshot(&self.seeds, &self.addr)
+    }
+
+    pub async fn shutdown(self) -> Result<()> {
+        self.router.shutdown().await?;
+        self.events.abort();
+        if let Some(nearby) = self.nearby {
+            nearby.abort();
+        }
+        Ok(())
+    }
+}
+
+/// Serve a tagged collection whenever the app runs.
+pub async fn seed_tag(store: &Store, tag: &str, hash: Hash) -> Result<()> {
+    store
+        .tags()
+        .set(format!("{SEED_PREFIX}{tag}"), HashAndFormat::hash_seq(hash))
+        .await?;
+    Ok(())
+}
+
+pub async fn unseed_tag(store: &Store, tag: &str) -> Result<()> {
+    store.tags().delete(format!("{SEED_PREFIX}{tag}")).await?;
+    Ok(())
+}
+
+/// Seeded tags and their collections.
+pub async fn seeded(store: &Store) -> Result<BTreeMap<String, Hash>> {
+    let mut tags = store.tags().list_prefix(SEED_PREFIX).await?;
+    let mut seeds = BTreeMap::new();
+    while let Some(info) = tags.next().await {
+        let info = info?;
+        let name = String::from_utf8_lossy(&info.name.0).to_string();
+        if let Some(tag) = name.strip_prefix(SEED_PREFIX) {
+            seeds.insert(tag.to_string(), info.hash);
+        }
+    }
+    Ok(seeds)
+}
+
+// The key of the seeding node , made on first use
+fn seed_key(store_path: &Path) -> Result<SecretKey> {
+    let path = store_path.join(KEY_FILE);
+    if let Ok(text) = std::fs::read_to_string(&path) {
+        let mut bytes = [0u8; 32];
+        hex::decode_to_slice(text.trim(), &mut bytes)?;
+        return Ok(SecretKey::from_bytes(&bytes));
+    }
+    let key = SecretKey::generate(rand::rngs::OsRng);
+    std::fs::write(&path, hex::encode(key.to_bytes()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(key)
+}
+
+async fn visited(mut visits: broadcast::Receiver<Visit>, peer: NodeId, wait: Duration) {
+    let came = tokio::time::timeout(wait, next_visit(&mut visits, Visit::Came(peer))).await;
+    if came == Ok(true) {
+        next_visit(&mut visits, Visit::Left(peer)).await;
+    }
+}
+
+// False when the node has stopped
+async fn next_visit(visits: &mut broadcast::Receiver<Visit>, visit: Visit) -> bool {
+    loop {
+        match visits.recv().await {
+            Ok(next) if next == visit => return true,
+            Err(broadcast::error::RecvError::Closed) => return false,
+            _ => {}
+        }
+    }
+}
+
+fn snapshot(seeds: &Seeds, addr: &NodeAddr) -> Vec<SeedStats> {
+    seeds
+        .lock()
+        .expect("seed lock")
+        .iter()
+        .map(|(tag, seed)| SeedStats {
+            tag: tag.clone(),
+            hash: seed.hash,
+            ticket: BlobTicket::new(addr.clone(), seed.hash, BlobFormat::HashSeq).to_string(),
+            bytes: seed.bytes,
+            peers: seed.peers.iter().copied().collect(),
+            requests: seed.requests,
+        })
+        .collect()
+}
+
+// Follow the provider and add up what each seeded collection served.
+// Requests are matched on the collection hash , single files fetched
+// on their own are served but not counted.
+async fn count_events(
+    mut events: mpsc::Receiver<Event>,
+    seeds: Seeds,
+    visits: broadcast::Sender<Visit>,
+    addr: NodeAddr,
+    mess: MessageOut,
+) {
+    let mut peers = HashMap::<u64, NodeId>::new();
+    let mut requests = HashMap::<(u64, u64), Hash>::new();
+    while let Some(event) = events.recv().await {
+        let (connection_id, request_id, bytes) = match event {
+            Event::ClientConnected {
+                connection_id,
+                node_id,
+                permitted,
+            } => {
+                peers.insert(connection_id, node_id);
+                permitted.send(true).await.ok();
+                visits.send(Visit::Came(node_id)).ok();
+                continue;
+            }
+            Event::ConnectionClosed { connection_id } => {
+                if let Some(node_id) = peers.remove(&connection_id) {
+                    visits.send(Visit::Left(node_id)).ok();
+                }
+                requests.retain(|(connection, _), _| *connection != connection_id);
+                continue;
+            }
+            Event::GetRequestReceived {
+                connection_id,
+                request_id,
+                hash,
+                ..
+            } => {
+                requests.insert((connection_id, request_id), hash);
+                continue;
+            }
+            Event::TransferCompleted {
+                connection_id,
+                request_id,
+                stats,
+            } => (connection_id, request_id, stats.payload_bytes_sent),
+            Event::TransferAborted {
+                connection_id,
+                request_id,
+                stats,
+            } => (
+                connection_id,
+                request_id,
+                stats.map_or(0, |stats| stats.payload_bytes_sent),
+            ),
+            _ => continue,
+        };
+        let Some(hash) = requests.remove(&(connection_id, request_id)) else {
+            continue;
+        };
+        let peer = peers.get(&connection_id).copied();
+        let counted = {
+            let mut seeds = seeds.lock().expect("seed lock");
+            let mut counted = false;
+            for seed in seeds.values_mut().filter(|seed| seed.hash == hash) {
+                seed.bytes += bytes;
+                seed.requests += 1;
+                seed.peers.extend(peer);
+                counted = true;
+            }
+            counted
+        };
+        if counted && let Err(err) = mess.seeding(snapshot(&seeds, &addr)).await {
+            warn!("seeding stats not sent {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_blobs::format::collection::Collection;
+
+    use super::*;
+    use crate::transport::memory_store;
+
+    #[tokio::test]
+    async fn served_allows_only_listed_collections() -> Result<()> {
+        let store = memory_store();
+        let seeded = store.add_bytes(b"seeded".to_vec()).await?.hash;
+        let offered = store.add_bytes(b"offered".to_vec()).await?.hash;
+        let private = store.add_bytes(b"private".to_vec()).await?.hash;
+        let seed_root = *[("a.txt", seeded)]
+            .into_iter()
+            .collect::<Collection>()
+            .store(&store)
+            .await?
+            .hash();
+        let offer_root = *[("b.txt", offered)]
+            .into_iter()
+            .collect::<Collection>()
+            .store(&store)
+            .await?
+            .hash();
+
+        let served = Served::default();
+        assert!(!served.allows(&store, seed_root).await?);
+        served.set_tagged(HashSet::from([seed_root]));
+        assert!(served.allows(&store, seed_root).await?);
+        assert!(served.allows(&store, seeded).await?);
+        assert!(!served.allows(&store, offered).await?);
+        assert!(!served.allows(&store, private).await?);
+
+        served.offer(offer_root);
+        assert!(served.allows(&store, offered).await?);
+        // Dropped seed tags stop being served , offers stay
+        served.set_tagged(HashSet::new());
+        assert!(!served.allows(&store, seeded).await?);
+        assert!(served.allows(&store, offer_root).await?);
+        assert!(!served.allows(&store, private).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fetched_once_the_peer_came_and_left() -> Result<()> {
+        let peer = SecretKey::generate(rand::rngs::OsRng).public();
+        let other = SecretKey::generate(rand::rngs::OsRng).public();
+        let (visits, _) = broadcast::channel(8);
+        let wait = Duration::from_millis(200);
+        let fetched = tokio::spawn(visited(visits.subscribe(), peer, wait));
+        visits.send(Visit::Came(other))?;
+        visits.send(Visit::Came(peer))?;
+        visits.send(Visit::Left(other))?;
+        // A long fetch outlasts the wait
+        tokio::time::sleep(wait * 2).await;
+        assert!(!fetched.is_finished());
+        visits.send(Visit::Left(peer))?;
+        tokio::time::timeout(wait, fetched).await??;
+
+        // Nobody came
+        tokio::time::timeout(wait * 2, visited(visits.subscribe(), peer, wait)).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,271 @@
+// Opening and moving the blob store
+// The store is a folder with a database and the blob files,
+// moving it is a plain copy that is checked before the old one goes.
+// In ephemeral mode the store lives in memory and is gone on exit.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use iroh_blobs::api::Store;
+use iroh_blobs::api::blobs::BlobStatus;
+use iroh_blobs::store::fs::FsStore;
+use iroh_blobs::store::fs::options::Options;
+use iroh_blobs::store::mem::MemStore;
+use n0_future::StreamExt;
+use walkdir::WalkDir;
+
+use super::gc::{BUSY_PREFIX, Collector, disk_usage, prepare_store};
+use crate::comms::MessageOut;
+
+/// Open the store at `path` with the gc under our control.
+pub async fn open_store(path: &Path) -> Result<(Store, Collector)> {
+    let (gc_config, collector) = Collector::new();
+    let options = Options {
+        gc: Some(gc_config),
+        ..Options::new(path)
+    };
+    let store = FsStore::load_with_opts(path.join("blobs.db"), options).await?;
+    prepare_store(&store).await?;
+    Ok(((*store).clone(), collector))
+}
+
+/// A store that is never written to disk , it has no gc.
+pub fn memory_store() -> Store {
+    (*MemStore::new()).clone()
+}
+
+/// Copy a closed store to `target` and open the copy.
+///
+/// The copy is checked file by file and then every tag must still
+/// point at a blob. On error the partial copy is removed and the
+/// source is left as it was.
+pub async fn copy_store(
+    source: &Path,
+    target: &Path,
+    tags: usize,
+    mess: &MessageOut,
+) -> Result<(Store, Collector)> {
+    let source = source.canonicalize()?;
+    anyhow::ensure!(
+        !resolve(target)?.starts_with(&source),
+        "{} is inside the store",
+        target.display()
+    );
+    let created = !target.exists();
+    if !created {
+        anyhow::ensure!(
+            std::fs::read_dir(target)?.next().is_none(),
+            "{} is not empty",
+            target.display()
+        );
+    }
+    let res = copy_and_open(&source, target, tags, mess).await;
+    if res.is_err() {
+        // A folder the user picked stays , only what was copied goes
+        if created {
+            let _ = std::fs::remove_dir_all(target);
+        } else if let Ok(entries) = std::fs::read_dir(target) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let _ = match path.is_dir() {
+                    true => std::fs::remove_dir_all(&path),
+                    false => std::fs::remove_file(&path),
+                };
+            }
+        }
+    }
+    res
+}
+
+// The absolute path with every link followed , the part that does not
+// exist yet is added back as it is
+fn resolve(path: &Path) -> Result<PathBuf> {
+    let path = std::path::absolute(path)?;
+    let mut base = path.as_path();
+    let mut missing = Vec::new();
+    while !base.exists() {
+        let name = base
+            .file_name()
+            .with_context(|| format!("{} can not be resolved", path.display()))?;
+        missing.push(name);
+        base = base
+            .parent()
+            .with_context(|| format!("{} can not be resolved", path.display()))?;
+    }
+    let mut resolved = base.canonicalize()?;
+    resolved.extend(missing.iter().rev());
+    Ok(resolved)
+}
+
+async fn copy_and_open(
+    source: &Path,
+    target: &Path,
+    tags: usize,
+    mess: &MessageOut,
+) -> Result<(Store, Collector)> {
+    let total = disk_usage(source);
+    let mut copied = 0;
+    for (from, to) in store_files(source, target)? {
+        if let Some(parent) = to.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let size = tokio::task::spawn_blocking({
+            let (from, to) = (from.clone(), to.clone());
+            move || std::fs::copy(from, to)
+        })
+        .await?
+        .with_context(|| format!("copying {}", from.display()))?;
+        copied += size;
+        mess.progress("Move store", copied as usize, total as usize)
+            .await?;
+    }
+    mess.complete("Move store").await?;
+
+    // Same files , same sizes
+    for (from, to) in store_files(source, target)? {
+        let (from_len, to_len) = (
+            std::fs::metadata(&from)?.len(),
+            std::fs::metadata(&to)?.len(),
+        );
+        anyhow::ensure!(from_len == to_len, "{} did not copy", from.display());
+    }
+    // and the copy opens with every tag in place
+    let (store, collector) = open_store(target).await?;
+    if let Err(err) = check_tags(&store, tags).await {
+        store.shutdown().await?;
+        return Err(err);
+    }
+    mess.progress_finish("Move store").await?;
+    Ok((store, collector))
+}
+
+/// Number of tags in the store.
+///
+/// Protection of running jobs is left out , opening the copy drops it.
+pub async fn count_tags(store: &Store) -> Result<usize> {
+    let mut tags = store.tags().list().await?;
+    let mut count = 0;
+    while let Some(info) = tags.next().await {
+        if !info?.name.0.starts_with(BUSY_PREFIX.as_bytes()) {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+// Every tag is there and points at a blob
+async fn check_tags(store: &Store, expected: usize) -> Result<()> {
+    let mut tags = store.tags().list().await?;
+    let mut count = 0;
+    while let Some(info) = tags.next().await {
+        let info = info?;
+        if info.name.0.starts_with(BUSY_PREFIX.as_bytes()) {
+            continue;
+        }
+        if store.blobs().status(info.hash).await? == BlobStatus::NotFound {
+            anyhow::bail!("tag {} lost its data", info.name);
+        }
+        count += 1;
+    }
+    anyhow::ensure!(count == expected, "{} of {} tags copied", count, expected);
+    Ok(())
+}
+
+// Pairs of (source, target) for every file in the store
+fn store_files(source: &Path, target: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
+    WalkDir::new(source)
+        .into_iter()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .map_or(true, |entry| entry.file_type().is_file())
+        })
+        .map(|entry| {
+            let path = entry?.into_path();
+            let relative = path.strip_prefix(source)?.to_path_buf();
+            Ok((path, target.join(relative)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_blobs::{Hash, HashAndFormat};
+
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sendme-store-{}-{}", name, std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn busy_tags_are_not_counted() -> Result<()> {
+        let store = memory_store();
+        let hash = store.add_bytes(b"kept".to_vec()).await?.hash;
+        store
+            .tags()
+            .set("out-kept", HashAndFormat::raw(hash))
+            .await?;
+        let before = count_tags(&store).await?;
+        store
+            .tags()
+            .set(
+                format!("{BUSY_PREFIX}fetch-x"),
+                HashAndFormat::raw(Hash::new(b"gone")),
+            )
+            .await?;
+        assert_eq!(count_tags(&store).await?, before);
+        // The busy tag points at nothing , it must not fail the check
+        check_tags(&store, before).await?;
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn failed_move_keeps_a_picked_folder() -> Result<()> {
+        // Not a store , the copy opens without the tag that is expected
+        let source = scratch("source");
+        std::fs::create_dir_all(&source)?;
+        std::fs::write(source.join("notes.txt"), b"not a store")?;
+        let (event_tx, _event_rx) = async_channel::unbounded();
+        let mess = MessageOut::new(event_tx);
+
+        let picked = scratch("picked");
+        std::fs::create_dir_all(&picked)?;
+        let res = copy_store(&source, &picked, 1, &mess).await;
+        let kept = picked.exists() && std::fs::read_dir(&picked)?.next().is_none();
+        let _ = std::fs::remove_dir_all(&picked);
+
+        let made = scratch("made");
+        let made_res = copy_store(&source, &made, 1, &mess).await;
+        let removed = !made.exists();
+        std::fs::remove_dir_all(&source)?;
+
+        assert!(res.is_err());
+        assert!(kept, "picked folder was removed or left dirty");
+        assert!(made_res.is_err());
+        assert!(removed, "created folder was left behind");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn target_through_a_link_is_inside_the_store() -> Result<()> {
+        let source = scratch("linked");
+        std::fs::create_dir_all(&source)?;
+        let link = scratch("link");
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink(&source, &link)?;
+        let (event_tx, _event_rx) = async_channel::unbounded();
+        let mess = MessageOut::new(event_tx);
+
+        let res = copy_store(&source, &link.join("moved"), 1, &mess).await;
+        let untouched = !source.join("moved").exists();
+        std::fs::remove_file(&link)?;
+        std::fs::remove_dir_all(&source)?;
+
+        let err = res.err().context("a target inside the store was taken")?;
+        assert!(err.to_string().contains("inside the store"));
+        assert!(untouched);
+        Ok(())
+    }
+}
@@ -0,0 +1,410 @@
+// Look through the tags in the blob store
+// every send and fetch leaves a named tag behind
+// this turns them into something the gui can show.
+//
+// Tags are named `<direction>/<root>-<date>`, older stores have
+// `<direction>-<rfc3339>`. The creation time and a free text note
+// live in a small json blob under an `info-` tag next to each tag.
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use iroh_blobs::api::Store;
+use iroh_blobs::api::blobs::BlobStatus;
+use iroh_blobs::api::proto::TagInfo;
+use iroh_blobs::format::collection::Collection;
+use iroh_blobs::{Hash, HashAndFormat};
+use n0_future::StreamExt;
+use serde_derive::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::gc::{BUSY_PREFIX, PIN_PREFIX, pinned};
+use super::meta::meta_index;
+use super::seed::{SEED_PREFIX, seeded};
+use super::verify::REF_PREFIX;
+
+/// Direction of the tags made by fetches.
+pub const INCOMING: &str = "incoming";
+/// Direction of the tags made by sends.
+pub const OUTGOING: &str = "outgoing";
+/// Direction of the copies taken for a peer.
+pub const REPLICA: &str = "replica";
+// Prefix of the tags holding the creation time and note
+const INFO_PREFIX: &str = "info-";
+
+// Stored next to a tag
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TagDetails {
+    // rfc3339
+    created: Option<String>,
+    note: String,
+}
+
+/// Summary of a tagged collection in the store.
+#[derive(Debug, Clone)]
+pub struct TagEntry {
+    pub tag: String,
+    pub hash: Hash,
+    /// Top level name of the collection ( file or folder )
+    pub name: String,
+    pub size: u64,
+    pub files: usize,
+    /// Every blob of the collection is in the store
+    pub complete: bool,
+    /// Kept out of expiry and the store quota
+    pub pinned: bool,
+    /// Served by the seeding node
+    pub seeded: bool,
+    pub created: Option<DateTime<Local>>,
+    pub note: String,
+}
+
+impl TagEntry {
+    /// `incoming`, `outgoing` or `replica` for the tags made by fetch, send and replication.
+    pub fn direction(&self) -> Option<&'static str> {
+        [INCOMING, OUTGOING, REPLICA]
+            .into_iter()
+            .find(|direction| self.tag.starts_with(direction))
+    }
+
+    /// The tag without the direction.
+    pub fn label(&self) -> &str {
+        match self.direction() {
+            Some(direction) => self.tag[direction.len()..].trim_start_matches(['/', '-']),
+            None => &self.tag,
+        }
+    }
+
+    /// Search the tag, root name, note, hash prefix and date.
+    pub fn matches(&self, search: &str) -> bool {
+        let search = search.trim().to_lowercase();
+        if search.is_empty() {
+            return true;
+        }
+        [&self.tag, &self.name, &self.note]
+            .iter()
+            .any(|text| text.to_lowercase().contains(&search))
+            || self.hash.to_hex().starts_with(&search)
+            || self
+                .created
+                .is_some_and(|date| date.format("%Y-%m-%d").to_string().contains(&search))
+    }
+}
+
+/// One entry of a collection as seen from the store.
+#[derive(Debug, Clone)]
+pub struct TreeItem {
+    pub name: String,
+    pub hash: Hash,
+    /// Size if the blob is complete
+    pub size: Option<u64>,
+}
+
+/// List the collections tagged with `prefix`, newest first.
+///
+/// An empty prefix lists every tag in the store except the pins and
+/// protection tags used by the gc.
+pub async fn list_tags(store: &Store, prefix: &str) -> Result<Vec<TagEntry>> {
+    let pins = pinned(store).await?;
+    let seeds = seeded(store).await?;
+    let mut tags = store.tags().list_prefix(prefix).await?;
+    let mut entries = Vec::new();
+    while let Some(info) = tags.next().await {
+        let info = info?;
+        if is_internal(&info.name.0) {
+            continue;
+        }
+        match summarize(store, &info).await {
+            Ok(mut entry) => {
+                entry.pinned = pins.contains(&entry.tag);
+                entry.seeded = seeds.contains_key(&entry.tag);
+                let details = details(store, &entry.tag).await;
+                entry.created = details
+                    .created
+                    .as_deref()
+                    .and_then(parse_date)
+                    .or(entry.created);
+                entry.note = details.note;
+                entries.push(entry);
+            }
+            Err(err) => warn!("skipping tag {} {err}", info.name),
+        }
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.created));
+    Ok(entries)
+}
+
+// Load the collection behind a tag and add up the sizes
+async fn summarize(store: &Store, info: &TagInfo) -> Result<TagEntry> {
+    let tag = String::from_utf8_lossy(&info.name.0).to_string();
+    // Raw blobs and unfinished downloads have no collection to load
+    let Ok(collection) = Collection::load(info.hash, store).await else {
+        let status = store.blobs().status(info.hash).await?;
+        return Ok(TagEntry {
+            name: format!("({})", info.hash.fmt_short()),
+            hash: info.hash,
+            size: match status {
+                BlobStatus::Complete { size } => size,
+                BlobStatus::Partial { size } => size.unwrap_or(0),
+                BlobStatus::NotFound => 0,
+            },
+            files: 0,
+            complete: matches!(status, BlobStatus::Complete { .. }),
+            pinned: false,
+            seeded: false,
+            created: tag_date(&tag),
+            note: String::new(),
+            tag,
+        });
+    };
+    let mut size = 0;
+    let mut complete = true;
+    for (_, hash) in collection.iter() {
+        match store.blobs().status(*hash).await? {
+            BlobStatus::Complete { size: blob_size } => size += blob_size,
+            _ => complete = false,
+        }
+    }
+    Ok(TagEntry {
+        name: root_name(&collection),
+        hash: info.hash,
+        size,
+        files: collection.len() - usize::from(meta_index(&collection).is_some()),
+        complete,
+        pinned: false,
+        seeded: false,
+        created: tag_date(&tag),
+        note: String::new(),
+        tag,
+    })
+}
+
+// Tags used by the gc, the notes, the store check and seeding , not shown
+pub(super) fn is_internal(name: &[u8]) -> bool {
+    [
+        PIN_PREFIX,
+        BUSY_PREFIX,
+        INFO_PREFIX,
+        REF_PREFIX,
+        SEED_PREFIX,
+    ]
+    .iter()
+    .any(|prefix| name.starts_with(prefix.as_bytes()))
+}
+
+/// Tag a new collection as `<direction>/<root>-<date>`.
+///
+/// A number is added when the name is taken.
+pub async fn new_tag(store: &Store, direction: &str, root: &str, hash: Hash) -> Result<String> {
+    let base = format!("{}/{}-{}", direction, root, Local::now().format("%Y-%m-%d"));
+    let mut name = base.clone();
+    let mut count = 1;
+    while store.tags().get(&name).await?.is_some() {
+        count += 1;
+        name = format!("{}-{}", base, count);
+    }
+    store
+        .tags()
+        .set(&name, HashAndFormat::hash_seq(hash))
+        .await?;
+    let details = TagDetails {
+        created: Some(Local::now().to_rfc3339()),
+        note: String::new(),
+    };
+    save_details(store, &name, &details).await?;
+    Ok(name)
+}
+
+/// Give a tag a new name , the pin, the seed and the note go with it.
+pub async fn rename_tag(store: &Store, from: &str, to: &str) -> Result<()> {
+    anyhow::ensure!(!to.is_empty(), "tag name is empty");
+    anyhow::ensure!(!is_internal(to.as_bytes()), "{} is a reserved name", to);
+    anyhow::ensure!(
+        store.tags().get(to).await?.is_none(),
+        "tag {} already exists",
+        to
+    );
+    store.tags().rename(from, to).await?;
+    for prefix in [PIN_PREFIX, SEED_PREFIX, INFO_PREFIX] {
+        let old = format!("{prefix}{from}");
+        if store.tags().get(&old).await?.is_some() {
+            store.tags().rename(old, format!("{prefix}{to}")).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Attach a free text note to a tag.
+pub async fn set_note(store: &Store, tag: &str, note: String) -> Result<()> {
+    let mut details = details(store, tag).await;
+    details.note = note;
+    save_details(store, tag, &details).await
+}
+
+// Creation time and note of a tag , empty if there are none
+async fn details(store: &Store, tag: &str) -> TagDetails {
+    let Ok(Some(info)) = store.tags().get(format!("{INFO_PREFIX}{tag}")).await else {
+        return TagDetails::default();
+    };
+    match store.blobs().get_bytes(info.hash).await {
+        Ok(data) => serde_json::from_slice(&data).unwrap_or_default(),
+        Err(err) => {
+            warn!("could not read tag info {err}");
+            TagDetails::default()
+        }
+    }
+}
+
+async fn save_details(store: &Store, tag: &str, details: &TagDetails) -> Result<()> {
+    let data = serde_json::to_vec(details)?;
+    let blob = store.add_bytes(data).temp_tag().await?;
+    store
+        .tags()
+        .set(format!("{INFO_PREFIX}{tag}"), *blob.hash())
+        .await?;
+    Ok(())
+}
+
+/// The files of a collection with their sizes.
+pub async fn collection_tree(store: &Store, hash: Hash) -> Result<Vec<TreeItem>> {
+    let collection = Collection::load(hash, store).await?;
+    let meta = meta_index(&collection);
+    let mut items = Vec::new();
+    for (i, (name, hash)) in collection.iter().enumerate() {
+        if Some(i) == meta {
+            continue;
+        }
+        let size = match store.blobs().status(*hash).await? {
+            BlobStatus::Complete { size } => Some(size),
+            _ => None,
+        };
+        items.push(TreeItem {
+            name: name.clone(),
+            hash: *hash,
+            size,
+        });
+    }
+    Ok(items)
+}
+
+/// Every blob reachable from the tags other than `except`.
+pub async fn referenced_hashes(store: &Store, except: &str) -> Result<HashSet<Hash>> {
+    let mut tags = store.tags().list().await?;
+    let mut roots = Vec::new();
+    while let Some(info) = tags.next().await {
+        let info = info?;
+        if info.name.0 != except.as_bytes() {
+            roots.push(info.hash);
+        }
+    }
+    let mut hashes = HashSet::new();
+    for root in roots {
+        hashes.insert(root);
+        if let Ok(collection) = Collection::load(root, store).await {
+            hashes.extend(collection.iter().map(|(_, hash)| *hash));
+        }
+    }
+    Ok(hashes)
+}
+
+/// Remove a tag , the data goes when nothing else protects it.
+pub async fn delete_tag(store: &Store, tag: &str) -> Result<()> {
+    store.tags().delete(tag).await?;
+    store.tags().delete(format!("{INFO_PREFIX}{tag}")).await?;
+    store.tags().delete(format!("{SEED_PREFIX}{tag}")).await?;
+    Ok(())
+}
+
+// The first component of the names is the shared file or folder
+pub(super) fn root_name(collection: &Collection) -> String {
+    collection
+        .iter()
+        .next()
+        .and_then(|(name, _)| name.split('/').next())
+        .unwrap_or("(empty)")
+        .to_string()
+}
+
+// Older tags are named <direction>-<rfc3339>
+fn tag_date(tag: &str) -> Option<DateTime<Local>> {
+    let (_, date) = tag.split_once('-')?;
+    parse_date(date)
+}
+
+fn parse_date(date: &str) -> Option<DateTime<Local>> {
+    DateTime::parse_from_rfc3339(date)
+        .ok()
+        .map(|date| date.with_timezone(&Local))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::memory_store;
+
+    fn entry(tag: &str) -> TagEntry {
+        TagEntry {
+            tag: tag.to_string(),
+            hash: Hash::new(b"collection"),
+            name: "Holiday".to_string(),
+            size: 0,
+            files: 0,
+            complete: true,
+            pinned: false,
+            seeded: false,
+            created: None,
+            note: "for ann".to_string(),
+        }
+    }
+
+    #[test]
+    fn label_drops_the_direction() {
+        assert_eq!(
+            entry("outgoing/Holiday-2025-01-02").label(),
+            "Holiday-2025-01-02"
+        );
+        assert_eq!(
+            entry("incoming-2025-01-02T10:00:00+00:00").label(),
+            "2025-01-02T10:00:00+00:00"
+        );
+        assert_eq!(entry("replica/docs").direction(), Some(REPLICA));
+        assert_eq!(entry("mine").label(), "mine");
+        assert_eq!(entry("mine").direction(), None);
+    }
+
+    #[test]
+    fn matches_tag_name_note_hash_and_date() {
+        let mut entry = entry("outgoing/trip");
+        entry.created = tag_date("outgoing-2025-01-02T10:00:00+00:00");
+        assert!(entry.matches(""));
+        assert!(entry.matches("TRIP"));
+        assert!(entry.matches("holiday"));
+        assert!(entry.matches(" ann "));
+        assert!(entry.matches(&entry.hash.to_hex()[..6]));
+        assert!(entry.matches("2025-01"));
+        assert!(!entry.matches("bob"));
+        // Only the start of the hash
+        assert!(!entry.matches(&entry.hash.to_hex()[6..12]));
+    }
+
+    #[test]
+    fn date_of_old_tags() {
+        let date = tag_date("incoming-2025-01-02T10:00:00+00:00").expect("date");
+        assert_eq!(date.to_utc().to_rfc3339(), "2025-01-02T10:00:00+00:00");
+        assert!(tag_date("outgoing/Holiday-2025-01-02").is_none());
+        assert!(tag_date("nodate").is_none());
+    }
+
+    #[tokio::test]
+    async fn new_tags_count_up() -> Result<()> {
+        let store = memory_store();
+        let hash = Hash::new(b"collection");
+        let first = new_tag(&store, OUTGOING, "docs", hash).await?;
+        let second = new_tag(&store, OUTGOING, "docs", hash).await?;
+        let today = Local::now().format("%Y-%m-%d");
+        assert_eq!(first, format!("outgoing/docs-{}", today));
+        assert_eq!(second, format!("outgoing/docs-{}-2", today));
+        Ok(())
+    }
+}
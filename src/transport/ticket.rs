@@ -0,0 +1,244 @@
+// Ticket making , what goes into a ticket.
+// AddrInfoOptions and apply_options are lifted from sendme.
+// Also the .sendme ticket file , a ticket with a human description.
+
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Result;
+use iroh::NodeAddr;
+use iroh_blobs::ticket::BlobTicket;
+use serde_derive::{Deserialize, Serialize};
+
+// Extension for saved tickets
+pub const TICKET_EXTENSION: &str = "sendme";
+
+/// Options to configure what is included in a [`NodeAddr`]
+#[derive(Copy, Clone, PartialEq, Eq, Default, Debug, Serialize, Deserialize)]
+pub enum AddrInfoOptions {
+    /// Only the Node ID is added.
+    ///
+    /// This usually means that iroh-dns discovery is used to find address information.
+    Id,
+    /// Includes the Node ID and both the relay URL, and the direct addresses.
+    #[default]
+    RelayAndAddresses,
+    /// Includes the Node ID and the relay URL.
+    Relay,
+    /// Includes the Node ID and the direct addresses.
+    Addresses,
+}
+
+impl AddrInfoOptions {
+    pub const ALL: [AddrInfoOptions; 4] = [
+        Self::Id,
+        Self::RelayAndAddresses,
+        Self::Relay,
+        Self::Addresses,
+    ];
+}
+
+impl Display for AddrInfoOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let val = match self {
+            Self::Id => "Id only",
+            Self::RelayAndAddresses => "Full",
+            Self::Relay => "Relay",
+            Self::Addresses => "Addresses",
+        };
+        write!(f, "{}", val)
+    }
+}
+
+/// Strip the address down to what was asked for.
+pub fn apply_options(addr: &mut NodeAddr, opts: AddrInfoOptions) {
+    match opts {
+        AddrInfoOptions::Id => {
+            addr.direct_addresses.clear();
+            addr.relay_url = None;
+        }
+        AddrInfoOptions::RelayAndAddresses => {
+            // nothing to do
+        }
+        AddrInfoOptions::Relay => {
+            addr.direct_addresses.clear();
+        }
+        AddrInfoOptions::Addresses => {
+            addr.relay_url = None;
+        }
+    }
+}
+
+/// A ticket saved to a `.sendme` file.
+///
+/// The description is written as `#` comment lines above the ticket
+/// so the file is readable and still works as plain text.
+pub struct TicketFile {
+    pub ticket: String,
+    pub description: String,
+}
+
+impl TicketFile {
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut text = String::new();
+        for line in self.description.lines() {
+            text.push_str(&format!("# {}\n", line));
+        }
+        text.push_str(&self.ticket);
+        text.push('\n');
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(Self::parse(&text))
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let description = text
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix('#'))
+            .map(|line| line.trim())
+            .collect::<Vec<_>>()
+            .join("\n");
+        Self {
+            ticket: clean_ticket(text),
+            description,
+        }
+    }
+}
+
+/// Pull the ticket out of pasted text.
+///
+/// Takes the first word that parses as a ticket , so comment lines , the
+/// `sendme receive` leader from the cli and prose around it are skipped.
+/// Without one a word that starts like a ticket is returned so the parse
+/// error says what is wrong with it , or nothing.
+pub fn clean_ticket(text: &str) -> String {
+    let words = text
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .flat_map(|line| line.split_whitespace())
+        // Quotes , brackets and full stops around a pasted ticket
+        .map(|word| word.trim_matches(|c: char| !c.is_ascii_alphanumeric()));
+    let mut broken = None;
+    for word in words {
+        if BlobTicket::from_str(word).is_ok() {
+            return word.to_string();
+        }
+        if broken.is_none() && word.starts_with("blob") {
+            broken = Some(word);
+        }
+    }
+    broken.unwrap_or("").to_string()
+}
+
+/// Does the text hold a blob ticket.
+pub fn is_ticket(text: &str) -> bool {
+    BlobTicket::from_str(&clean_ticket(text)).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+    use iroh_blobs::{BlobFormat, Hash};
+
+    use super::*;
+
+    fn ticket() -> String {
+        let node_id = SecretKey::generate(rand::thread_rng()).public();
+        BlobTicket::new(node_id.into(), Hash::new(b"ticket"), BlobFormat::HashSeq).to_string()
+    }
+
+    #[test]
+    fn plain_ticket() {
+        let ticket = ticket();
+        assert_eq!(clean_ticket(&ticket), ticket);
+        assert_eq!(clean_ticket(&format!("  {}\n", ticket)), ticket);
+        assert!(is_ticket(&ticket));
+    }
+
+    #[test]
+    fn cli_leader() {
+        let ticket = ticket();
+        assert_eq!(clean_ticket(&format!("sendme receive {}", ticket)), ticket);
+        assert_eq!(clean_ticket(&format!("sendme recv {}", ticket)), ticket);
+    }
+
+    #[test]
+    fn prose_around_the_ticket() {
+        let ticket = ticket();
+        let text = format!("here is it: \"{}\". Have fun", ticket);
+        assert_eq!(clean_ticket(&text), ticket);
+        assert!(!is_ticket("here is nothing"));
+        assert_eq!(clean_ticket("here is nothing"), "");
+    }
+
+    #[test]
+    fn broken_ticket_is_kept_for_the_error() {
+        assert_eq!(clean_ticket("get blobaaaa please"), "blobaaaa");
+        assert!(!is_ticket("blobaaaa"));
+    }
+
+    #[test]
+    fn ticket_file_roundtrip() {
+        let ticket = ticket();
+        let file = TicketFile {
+            ticket: ticket.clone(),
+            description: "holiday photos\nshared 2025-01-02".to_string(),
+        };
+        let dir = std::env::temp_dir().join(format!("sendme-ticket-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("share.sendme");
+        file.save(&path).unwrap();
+        let loaded = TicketFile::load(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(loaded.ticket, ticket);
+        assert_eq!(loaded.description, file.description);
+    }
+
+    #[test]
+    fn comment_lines_are_the_description() {
+        let ticket = ticket();
+        let parsed = TicketFile::parse(&format!("# from ann\n#  two files\n\n{}\n", ticket));
+        assert_eq!(parsed.ticket, ticket);
+        assert_eq!(parsed.description, "from ann\ntwo files");
+    }
+
+    fn full_addr() -> NodeAddr {
+        let node_id = SecretKey::generate(rand::thread_rng()).public();
+        NodeAddr::from_parts(
+            node_id,
+            Some("https://relay.example.com".parse().unwrap()),
+            ["192.0.2.1:4433".parse().unwrap()],
+        )
+    }
+
+    #[test]
+    fn apply_options_strips_the_address() {
+        let mut addr = full_addr();
+        apply_options(&mut addr, AddrInfoOptions::RelayAndAddresses);
+        assert!(addr.relay_url.is_some());
+        assert_eq!(addr.direct_addresses.len(), 1);
+
+        let mut addr = full_addr();
+        apply_options(&mut addr, AddrInfoOptions::Relay);
+        assert!(addr.relay_url.is_some());
+        assert!(addr.direct_addresses.is_empty());
+
+        let mut addr = full_addr();
+        apply_options(&mut addr, AddrInfoOptions::Addresses);
+        assert!(addr.relay_url.is_none());
+        assert_eq!(addr.direct_addresses.len(), 1);
+
+        let mut addr = full_addr();
+        let node_id = addr.node_id;
+        apply_options(&mut addr, AddrInfoOptions::Id);
+        assert!(addr.relay_url.is_none());
+        assert!(addr.direct_addresses.is_empty());
+        assert_eq!(addr.node_id, node_id);
+    }
+}
@@ -0,0 +1,268 @@
+// Store integrity
+// Disks rot and files served by reference change under the store,
+// neither is noticed until someone fetches the blob.
+//
+// A check reads every complete blob back through the bao export, the
+// store validates the data against the outboard on the way out. The
+// original file of a referenced blob is kept under a `ref-<hash>` tag
+// so it can be found again when the blob breaks.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use iroh_blobs::api::Store;
+use iroh_blobs::api::blobs::{
+    AddPathOptions, AddProgressItem, BlobStatus, EncodedItem, ImportMode,
+};
+use iroh_blobs::format::collection::Collection;
+use iroh_blobs::protocol::ChunkRanges;
+use iroh_blobs::{BlobFormat, Hash};
+use n0_future::StreamExt;
+use tracing::{info, warn};
+
+use super::gc::unpin_tag;
+use super::tags::{delete_tag, is_internal};
+use crate::comms::MessageOut;
+
+// Prefix of the tags holding the source path of a referenced blob
+pub const REF_PREFIX: &str = "ref-";
+
+// Progress is sent after this many bytes
+const PROGRESS_STEP: u64 = 8 * 1024 * 1024;
+
+/// A blob that failed the check.
+#[derive(Debug, Clone)]
+pub struct Damaged {
+    pub hash: Hash,
+    pub size: u64,
+    pub error: String,
+    /// The original file , for blobs served by reference
+    pub source: Option<PathBuf>,
+    /// Tags of the collections that hold the blob
+    pub tags: Vec<String>,
+}
+
+/// Result of a store check.
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    pub checked: usize,
+    pub bytes: u64,
+    pub damaged: Vec<Damaged>,
+}
+
+/// Read back every complete blob and report the ones that do not match.
+pub async fn verify_store(store: &Store, mess: &MessageOut) -> Result<VerifyReport> {
+    let mut hashes = store.blobs().list().stream().await?;
+    let mut complete = Vec::new();
+    while let Some(hash) = hashes.next().await {
+        let hash = hash?;
+        if let BlobStatus::Complete { size } = store.blobs().status(hash).await? {
+            complete.push((hash, size));
+        }
+    }
+    let total = complete.iter().map(|(_, size)| *size).sum::<u64>();
+    let owners = tag_owners(store).await?;
+
+    let mut report = VerifyReport::default();
+    let mut progress = Progress {
+        mess,
+        done: 0,
+        reported: 0,
+        total,
+    };
+    for (hash, size) in complete {
+        if let Err(err) = check_blob(store, hash, size, &mut progress).await {
+            warn!("blob {} is damaged {err}", hash);
+            report.damaged.push(Damaged {
+                hash,
+                size,
+                error: err.to_string(),
+                source: source_path(store, hash).await?,
+                tags: owners.get(&hash).cloned().unwrap_or_default(),
+            });
+        }
+        report.checked += 1;
+        report.bytes += size;
+    }
+    mess.complete("Verify store").await?;
+    drop_stale_sources(store).await?;
+    mess.progress_finish("Verify store").await?;
+    Ok(report)
+}
+
+// Bytes read back so far
+struct Progress<'a> {
+    mess: &'a MessageOut,
+    done: u64,
+    reported: u64,
+    total: u64,
+}
+
+impl Progress<'_> {
+    async fn add(&mut self, read: u64) -> Result<()> {
+        self.done += read;
+        if self.done - self.reported >= PROGRESS_STEP || self.done == self.total {
+            self.reported = self.done;
+            self.mess
+                .progress("Verify store", self.done as usize, self.total as usize)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+// Run the blob through the validating export
+async fn check_blob(
+    store: &Store,
+    hash: Hash,
+    size: u64,
+    progress: &mut Progress<'_>,
+) -> Result<()> {
+    let mut stream = store.blobs().export_bao(hash, ChunkRanges::all()).stream();
+    while let Some(item) = stream.next().await {
+        match item {
+            EncodedItem::Size(found) if found != size => {
+                anyhow::bail!("size is {} , expected {}", found, size)
+            }
+            EncodedItem::Leaf(leaf) => progress.add(leaf.data.len() as u64).await?,
+            EncodedItem::Error(cause) => anyhow::bail!("{}", cause),
+            EncodedItem::Done => return Ok(()),
+            _ => {}
+        }
+    }
+    anyhow::bail!("read stopped early")
+}
+
+/// Import the original file of a damaged blob again.
+///
+/// The copy replaces the broken entry, the file must still have the
+/// content it had when it was shared.
+pub async fn repair_blob(store: &Store, hash: Hash, mess: &MessageOut) -> Result<()> {
+    let path = source_path(store, hash)
+        .await?
+        .context("the original file of this blob is not known")?;
+    anyhow::ensure!(path.is_file(), "{} is gone", path.display());
+    let mut stream = store
+        .blobs()
+        .add_path_with_opts(AddPathOptions {
+            path: path.clone(),
+            mode: ImportMode::Copy,
+            format: BlobFormat::Raw,
+        })
+        .stream()
+        .await;
+    let name = path.display().to_string();
+    let found = loop {
+        match stream
+            .next()
+            .await
+            .context("import stream ended without a tag")?
+        {
+            AddProgressItem::Size(size) => mess.progress(&name, 0, size as usize).await?,
+            AddProgressItem::Error(cause) => anyhow::bail!("error importing {}: {}", name, cause),
+            AddProgressItem::Done(tt) => {
+                mess.progress_finish(&name).await?;
+                break *tt.hash();
+            }
+            _ => {}
+        }
+    };
+    anyhow::ensure!(
+        found == hash,
+        "{} has changed since it was shared , the old content is gone",
+        path.display()
+    );
+    // The store keeps its own copy now
+    store.tags().delete(format!("{REF_PREFIX}{hash}")).await?;
+    let size = std::fs::metadata(&path)?.len();
+    let mut progress = Progress {
+        mess,
+        done: 0,
+        reported: 0,
+        total: size,
+    };
+    check_blob(store, hash, size, &mut progress).await?;
+    mess.progress_finish("Verify store").await?;
+    info!("repaired {} from {}", hash, path.display());
+    Ok(())
+}
+
+/// Remove the collections holding a damaged blob , the gc does the rest.
+pub async fn drop_blob(store: &Store, hash: Hash) -> Result<Vec<String>> {
+    let tags = tag_owners(store).await?.remove(&hash).unwrap_or_default();
+    for tag in tags.iter() {
+        delete_tag(store, tag).await?;
+        unpin_tag(store, tag).await?;
+    }
+    store.tags().delete(format!("{REF_PREFIX}{hash}")).await?;
+    Ok(tags)
+}
+
+/// Remember where the data of a referenced blob lives.
+pub async fn record_source(store: &Store, hash: Hash, path: &Path) -> Result<()> {
+    let blob = store
+        .add_bytes(path.to_string_lossy().to_string())
+        .temp_tag()
+        .await?;
+    store
+        .tags()
+        .set(format!("{REF_PREFIX}{hash}"), *blob.hash())
+        .await?;
+    Ok(())
+}
+
+async fn source_path(store: &Store, hash: Hash) -> Result<Option<PathBuf>> {
+    let Some(info) = store.tags().get(format!("{REF_PREFIX}{hash}")).await? else {
+        return Ok(None);
+    };
+    let path = store.blobs().get_bytes(info.hash).await?;
+    Ok(Some(PathBuf::from(String::from_utf8_lossy(&path).as_ref())))
+}
+
+// Sources of blobs the gc has removed
+pub(super) async fn drop_stale_sources(store: &Store) -> Result<()> {
+    let mut tags = store.tags().list_prefix(REF_PREFIX).await?;
+    let mut stale = Vec::new();
+    while let Some(info) = tags.next().await {
+        let name = String::from_utf8_lossy(&info?.name.0).to_string();
+        let Some(hash) = name
+            .strip_prefix(REF_PREFIX)
+            .and_then(|hash| hash.parse::<Hash>().ok())
+        else {
+            continue;
+        };
+        if store.blobs().status(hash).await? == BlobStatus::NotFound {
+            stale.push(name);
+        }
+    }
+    for name in stale {
+        store.tags().delete(name).await?;
+    }
+    Ok(())
+}
+
+// Which tags reach each blob
+async fn tag_owners(store: &Store) -> Result<HashMap<Hash, Vec<String>>> {
+    let mut tags = store.tags().list().await?;
+    let mut roots = Vec::new();
+    while let Some(info) = tags.next().await {
+        let info = info?;
+        if !is_internal(&info.name.0) {
+            roots.push((String::from_utf8_lossy(&info.name.0).to_string(), info.hash));
+        }
+    }
+    let mut owners = HashMap::<Hash, Vec<String>>::new();
+    for (tag, root) in roots {
+        owners.entry(root).or_default().push(tag.clone());
+        if let Ok(collection) = Collection::load(root, store).await {
+            for (_, hash) in collection.iter() {
+                let tags = owners.entry(*hash).or_default();
+                if !tags.contains(&tag) {
+                    tags.push(tag.clone());
+                }
+            }
+        }
+    }
+    Ok(owners)
+}
@@ -0,0 +1,205 @@
+// Watched share
+// Serve a folder that changes, every change is imported as a new version
+// of the collection with its own tag and ticket.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use humansize::{DECIMAL, format_size};
+use iroh::Watcher;
+use iroh_blobs::BlobFormat;
+use iroh_blobs::api::Store;
+use iroh_blobs::api::TempTag;
+use iroh_blobs::format::collection::Collection;
+use iroh_blobs::ticket::BlobTicket;
+use notify::{RecursiveMode, Watcher as _};
+use tokio::sync::oneshot;
+use tracing::warn;
+
+use super::gc::Busy;
+use super::limit::Limits;
+use super::meta::CollectionMeta;
+use super::net::NetOptions;
+use super::offer::{FileStamp, data_sources, import_files, start_router};
+use super::tags::{OUTGOING, new_tag};
+use super::ticket::apply_options;
+use super::{ImportChoice, SendOptions, list_tags};
+use crate::comms::MessageOut;
+
+// Wait for the folder to be quiet this long before importing
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// One published version of a watched share.
+#[derive(Debug, Clone)]
+pub struct ShareVersion {
+    pub version: usize,
+    pub ticket: String,
+    pub created: DateTime<Local>,
+    pub files: usize,
+    pub size: u64,
+}
+
+// Files in the current version, kept so unchanged files are not imported again
+type FileMap = BTreeMap<String, (FileStamp, TempTag, u64)>;
+
+/// Serve a folder and publish a new version whenever it changes.
+pub async fn watch_share(
+    path: PathBuf,
+    options: SendOptions,
+    net: NetOptions,
+    limits: Limits,
+    mess: MessageOut,
+    store: Store,
+    mut stop: oneshot::Receiver<()>,
+) -> Result<()> {
+    let path = path.canonicalize()?;
+    anyhow::ensure!(path.is_dir(), "{} is not a folder", path.display());
+    let root = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    // The files are expected to change, old versions must keep their data
+    let options = SendOptions {
+        import: ImportChoice::Copy,
+        ..options
+    };
+
+    // notify runs on its own thread, pass the events over a channel
+    let (change_tx, change_rx) = async_channel::unbounded();
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+            Ok(event) if event.kind.is_access() => {}
+            Ok(_) => {
+                let _ = change_tx.send_blocking(());
+            }
+            Err(err) => warn!("watch error {err}"),
+        })?;
+    watcher.watch(&path, RecursiveMode::Recursive)?;
+    mess.info(format!("Watching {}", path.display()).as_str())
+        .await?;
+
+    let router = start_router(&store, &mess, options.ticket_type, &net, &limits).await?;
+    let mut addr = router.endpoint().node_addr().initialized().await;
+    apply_options(&mut addr, options.ticket_type);
+
+    let mut files = FileMap::new();
+    let mut current = None;
+    // Keeps the version being served , the tag can be deleted meanwhile
+    let mut busy: Option<Busy> = None;
+    let mut version = 0;
+    loop {
+        match import_version(&path, &options, &store, &mess, &mut files).await {
+            Ok((temp_tag, size)) if current.as_ref() != Some(temp_tag.hash()) => {
+                version += 1;
+                let hash = *temp_tag.hash();
+                let created = Local::now();
+                if !options.ephemeral {
+                    new_tag(&store, OUTGOING, &root, hash).await?;
+                    let next = Busy::protect(&store, "watch", hash).await?;
+                    if let Some(old) = busy.replace(next) {
+                        old.release().await?;
+                    }
+                }
+                let ticket = BlobTicket::new(addr.clone(), hash, BlobFormat::HashSeq).to_string();
+                mess.info(
+                    format!(
+                        "Version {} , {} files , {}",
+                        version,
+                        files.len(),
+                        format_size(size, DECIMAL)
+                    )
+                    .as_str(),
+                )
+                .await?;
+                mess.share_version(ShareVersion {
+                    version,
+                    ticket: ticket.clone(),
+                    created,
+                    files: files.len(),
+                    size,
+                })
+                .await?;
+                mess.send_ticket(ticket).await?;
+                if !options.ephemeral {
+                    mess.shares(list_tags(&store, OUTGOING).await?).await?;
+                }
+                current = Some(hash);
+            }
+            // Nothing changed in the content
+            Ok(_) => {}
+            // Keep serving the last good version
+            Err(err) => mess.error(format!("{}", err).as_str()).await?,
+        }
+
+        // Wait for a change
+        tokio::select! {
+            _ = &mut stop => break,
+            change = change_rx.recv() => change?,
+        }
+        // and then for things to settle
+        while let Ok(change) = tokio::time::timeout(SETTLE_TIME, change_rx.recv()).await {
+            change?;
+        }
+    }
+    drop(watcher);
+    router.shutdown().await?;
+    if let Some(busy) = busy {
+        busy.release().await?;
+    }
+    mess.info("Stopped serving").await?;
+    Ok(())
+}
+
+// Import the changed files and store the new collection
+async fn import_version(
+    path: &Path,
+    options: &SendOptions,
+    store: &Store,
+    mess: &MessageOut,
+    files: &mut FileMap,
+) -> Result<(TempTag, u64)> {
+    let mut next = FileMap::new();
+    let mut stamps = BTreeMap::new();
+    let mut changed = Vec::new();
+    let sources = data_sources(path)?;
+    let meta = options
+        .preserve_metadata
+        .then(|| CollectionMeta::collect(&[path.to_path_buf()], &sources))
+        .transpose()?;
+    for (name, file) in sources {
+        // Files can go away while walking
+        let Ok(stamp) = FileStamp::new(name.clone(), file.clone()) else {
+            continue;
+        };
+        match files.remove(&name) {
+            Some((old, tag, size)) if old.same(&stamp) => {
+                next.insert(name, (stamp, tag, size));
+            }
+            _ => {
+                stamps.insert(name.clone(), stamp);
+                changed.push((name, file));
+            }
+        }
+    }
+    // Stamps are from before the import, a file edited during import is picked up next time
+    for (name, tag, size, _) in import_files(changed, options, store, mess.clone()).await? {
+        if let Some(stamp) = stamps.remove(&name) {
+            next.insert(name, (stamp, tag, size));
+        }
+    }
+    *files = next;
+    let size = files.values().map(|(_, _, size)| *size).sum::<u64>();
+    let mut collection = files
+        .iter()
+        .map(|(name, (_, tag, _))| (name.clone(), *tag.hash()))
+        .collect::<Collection>();
+    let _meta_tag = match meta {
+        Some(meta) => Some(meta.add_to(&mut collection, store).await?),
+        None => None,
+    };
+    let temp_tag = collection.store(store).await?;
+    Ok((temp_tag, size))
+}
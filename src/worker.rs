@@ -7,33 +7,91 @@ use std::{path::PathBuf, time::Duration};
 use crate::comms::{Command, Event, MessageOut};
 use anyhow::Result;
 use async_channel::{Receiver, Sender};
-use iroh_blobs::store::fs::FsStore;
-use n0_future::StreamExt;
-use tokio::time::{Instant, interval};
+use humansize::{DECIMAL, format_size};
+use iroh::NodeAddr;
+use iroh_blobs::Hash;
+use iroh_blobs::api::Store;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, interval, sleep_until};
 use tracing::{info, warn};
 
-use crate::transport::{receive, send};
+use crate::transport::{
+    Collector, GcOptions, Inbox, Limits, NetOptions, OUTGOING, Offer, Peers, ReplicaState,
+    ReplicaStatus, Seeder, Share, collect_garbage, collection_tree, copy_store, count_tags,
+    delete_tag, drop_blob, dry_run, export_bundle, export_stored, import_bundle, list_tags,
+    memory_store, open_store, pin_tag, preview, push_offer, push_replica, receive, rename_tag,
+    repair_blob, seed_tag, seeded, send, serve, set_note, unpin_tag, unseed_tag, verify_store,
+    watch_share,
+};
+
+// Time an accepted push has for the receiver to start the fetch
+const FETCH_WAIT: Duration = Duration::from_secs(600);
+
+// An error the worker can not carry on from , the command loop stops
+#[derive(Debug)]
+struct Fatal(String);
+
+impl std::fmt::Display for Fatal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Fatal {}
 
 pub struct Worker {
     pub command_rx: Receiver<Command>,
     pub mess: MessageOut,
     pub timer_out: Sender<TimerCommands>,
     pub store_path: PathBuf,
-    pub store: FsStore,
+    pub store: Store,
+    // Stop signal for the running share
+    pub share: Option<oneshot::Sender<()>>,
+    pub share_task: Option<JoinHandle<()>>,
+    // Garbage collection
+    // None for the in-memory store , it has no gc
+    pub collector: Option<Collector>,
+    pub gc_options: GcOptions,
+    pub gc_due: Option<Instant>,
+    // Serves the seeded tags , None when there are none
+    pub seeder: Option<Seeder>,
+    // Replication
+    pub peers: Peers,
+    pub replicate_shares: bool,
+    // Offers to and from contacts , shared with the gui
+    pub inbox: Inbox,
+    // Pushes not yet fetched , the node stays up for the receivers
+    pub pushes: usize,
+    pub push_done_tx: Sender<()>,
+    pub push_done_rx: Receiver<()>,
+    // Settings for every endpoint
+    pub net: NetOptions,
+    // Rates , shared with the gui
+    pub limits: Limits,
 }
 
 pub struct WorkerHandle {
     pub command_tx: Sender<Command>,
     pub event_rx: Receiver<Event>,
+    // Set straight from the gui so they change while a fetch holds the
+    // command loop
+    pub limits: Limits,
+    // Answered straight from the gui for the same reason
+    pub inbox: Inbox,
 }
 
 impl Worker {
-    pub fn spawn(store_path: PathBuf) -> WorkerHandle {
+    pub fn spawn(store_path: PathBuf, ephemeral: bool) -> WorkerHandle {
         let (command_tx, command_rx) = async_channel::bounded(16);
         let (event_tx, event_rx) = async_channel::bounded(16);
+        let limits = Limits::default();
+        let inbox = Inbox::default();
         let handle = WorkerHandle {
             command_tx,
             event_rx,
+            limits: limits.clone(),
+            inbox: inbox.clone(),
         };
         // Spawn a new worker as a seperate thread.
         //  egui is sync the worker is async , comms are a channel of commands and events
@@ -44,9 +102,10 @@ impl Worker {
                 .build()
                 .expect("failed to start tokio runtime");
             rt.block_on(async move {
-                let mut worker = Worker::start(command_rx, event_tx, store_path)
-                    .await
-                    .expect("Worker failed to start");
+                let mut worker =
+                    Worker::start(command_rx, event_tx, store_path, ephemeral, limits, inbox)
+                        .await
+                        .expect("Worker failed to start");
                 if let Err(err) = worker.run().await {
                     warn!("worker stopped with error {err:?}");
                 }
@@ -60,6 +119,9 @@ impl Worker {
         command_rx: async_channel::Receiver<Command>,
         event_tx: async_channel::Sender<Event>,
         store_path: PathBuf,
+        ephemeral: bool,
+        limits: Limits,
+        inbox: Inbox,
     ) -> Result<Self> {
         let mess = MessageOut::new(event_tx.clone());
         // Channel for the timer
@@ -68,32 +130,69 @@ impl Worker {
         let timer = TimerTask::new(mess.clone());
         // Run the timer
         timer.run(timer_in);
-        // Create the blob store
-        let store = iroh_blobs::store::fs::FsStore::load(&store_path).await?;
+        // Create the blob store , gc only runs when the collector asks
+        let (store, collector) = if ephemeral {
+            (memory_store(), None)
+        } else {
+            let (store, collector) = open_store(&store_path).await?;
+            (store, Some(collector))
+        };
+        // Pushes report back here once they are fetched
+        let (push_done_tx, push_done_rx) = async_channel::unbounded();
         // Make the worker
         Ok(Self {
             command_rx,
             mess,
-            timer_out: timer_out,
+            timer_out,
             store_path,
             store,
+            share: None,
+            share_task: None,
+            collector,
+            gc_options: GcOptions::default(),
+            gc_due: None,
+            seeder: None,
+            peers: Peers::default(),
+            replicate_shares: false,
+            inbox,
+            pushes: 0,
+            push_done_tx,
+            push_done_rx,
+            net: NetOptions::default(),
+            limits,
         })
     }
 
     async fn run(&mut self) -> Result<()> {
         // the actual runner for the worker
         info!("Starting  the worker");
-        loop { 
-            // strictly does not need the select 
-            // as there is only one thing (for now)
+        loop {
+            // commands from the gui and the scheduled clean up
+            let gc_due = self.gc_due;
             tokio::select! {
                 command = self.command_rx.recv() => {
                     let command = command?;
                     if let Err(err ) = self.handle_command(command).await{
+                        if err.is::<Fatal>() {
+                            return Err(err);
+                        }
                         self.mess.error(format!("{}",err).as_str()).await?;
                         warn!("command failed {err}");
                     }
                 }
+                // A push was fetched or given up on , the node may go
+                _ = self.push_done_rx.recv() => {
+                    self.pushes -= 1;
+                    if let Err(err) = self.update_seeds().await {
+                        warn!("seeding update failed {err}");
+                    }
+                }
+                _ = sleep_until(gc_due.unwrap_or_else(Instant::now)), if gc_due.is_some() => {
+                    if let Err(err) = self.collect_garbage().await {
+                        self.mess.error(format!("{}",err).as_str()).await?;
+                        warn!("scheduled gc failed {err}");
+                    }
+                }
             }
         }
     }
@@ -104,41 +203,337 @@ impl Worker {
         match command {
             Command::Setup { callback } => {
                 // lodge the redraw callback into the message updater
-                let _ = self.mess.set_callback(callback).await?;
+                self.mess.set_callback(callback).await?;
                 // Say ready
                 self.mess.correct("Ready...").await?;
-                // Show exisiting tags for later work ( replication worker , not yet)
-                let mut tags = self.store.tags().list().await.unwrap();
-                while let Some(event) = tags.next().await {
-                    let event = event?;
-                    info!("{} {}", event.name, event.hash);
+                if self.collector.is_some() {
+                    info!("blob store at {}", self.store_path.display());
+                } else {
+                    self.mess
+                        .info("Ephemeral mode , nothing is kept after exit")
+                        .await?;
                 }
-                return Ok(());
+                Ok(())
             }
-            // This needs commands to finish
-            // TODO add a cancellation ticket in here.
-            Command::Send(path) => {
+            // Import and then serve in the background until StopShare
+            Command::Send((paths, options)) => {
+                self.stop_share();
                 self.start_timer().await?;
-                match send(path, self.mess.clone(), self.store.clone()).await {
-                    Ok(_) => {
-                        self.reset_timer().await?;
-                        self.mess.finished().await?
+                self.limits.share.set(options.upload_limit);
+                match send(paths, options, self.mess.clone(), self.store.clone()).await {
+                    Ok(share) => {
+                        let hash = share.hash;
+                        self.start_share(share);
+                        self.list_shares().await?;
+                        if self.replicate_shares && self.collector.is_some() {
+                            self.replicate_share(hash).await?;
+                        }
                     }
                     Err(err) => {
                         self.reset_timer().await?;
                         return Err(err);
                     }
                 }
-                return Ok(());
+                Ok(())
+            }
+
+            // Walk the files without importing them
+            Command::DryRun(paths) => {
+                let report = dry_run(&paths, &self.store, self.mess.clone()).await?;
+                self.mess
+                    .info(
+                        format!(
+                            "Dry run , {} files , {}",
+                            report.files,
+                            format_size(report.total, DECIMAL)
+                        )
+                        .as_str(),
+                    )
+                    .await?;
+                self.mess.dry_run(report).await?;
+                Ok(())
+            }
+
+            // Serve a folder , publish a new version on change
+            Command::WatchShare((path, options)) => {
+                self.stop_share();
+                self.start_timer().await?;
+                self.limits.share.set(options.upload_limit);
+                let stop = self.share_stop();
+                self.spawn_share(watch_share(
+                    path,
+                    options,
+                    self.share_net(),
+                    self.limits.clone(),
+                    self.mess.clone(),
+                    self.store.clone(),
+                    stop,
+                ));
+                Ok(())
+            }
+
+            // Serve a previous share again , no import needed
+            Command::Reshare((hash, ticket_type)) => {
+                self.stop_share();
+                self.start_timer().await?;
+                self.start_share(Share::existing(hash, ticket_type));
+                Ok(())
+            }
+
+            Command::StopShare => {
+                self.stop_share();
+                self.reset_timer().await?;
+                self.mess.finished().await?;
+                Ok(())
+            }
+
+            Command::ListShares => {
+                self.list_shares().await?;
+                Ok(())
+            }
+
+            // Store browser
+            Command::ListStore => {
+                self.list_store().await?;
+                Ok(())
+            }
+
+            Command::DeleteTag(tag) => {
+                delete_tag(&self.store, &tag).await?;
+                unpin_tag(&self.store, &tag).await?;
+                self.mess
+                    .info(format!("Deleted tag {}", tag).as_str())
+                    .await?;
+                self.update_seeds().await?;
+                self.list_store().await?;
+                self.list_shares().await?;
+                Ok(())
+            }
+
+            Command::RenameTag((from, to)) => {
+                rename_tag(&self.store, &from, &to).await?;
+                self.mess
+                    .info(format!("Renamed {} to {}", from, to).as_str())
+                    .await?;
+                self.update_seeds().await?;
+                self.list_store().await?;
+                self.list_shares().await?;
+                Ok(())
+            }
+
+            Command::TagNote((tag, note)) => {
+                set_note(&self.store, &tag, note).await?;
+                self.list_store().await?;
+                Ok(())
+            }
+
+            Command::InspectTag(hash) => {
+                let items = collection_tree(&self.store, hash).await?;
+                self.mess.tree(hash, items).await?;
+                Ok(())
+            }
+
+            // Write a stored collection out to a folder
+            Command::ExportTag((hash, target)) => {
+                self.start_timer().await?;
+                let res = export_stored(&self.store, hash, target.clone(), self.mess.clone()).await;
+                self.reset_timer().await?;
+                res?;
+                self.mess
+                    .correct(format!("Exported to {}", target.display()).as_str())
+                    .await?;
+                Ok(())
+            }
+
+            // One file with the whole collection
+            Command::ExportBundle((hash, path)) => {
+                self.start_timer().await?;
+                let res = export_bundle(&self.store, hash, &path, &self.mess).await;
+                self.reset_timer().await?;
+                let size = res?;
+                self.mess
+                    .correct(
+                        format!(
+                            "Bundle written to {} , {}",
+                            path.display(),
+                            format_size(size, DECIMAL)
+                        )
+                        .as_str(),
+                    )
+                    .await?;
+                Ok(())
+            }
+
+            Command::PinTag((tag, hash)) => {
+                pin_tag(&self.store, &tag, hash).await?;
+                self.list_store().await?;
+                Ok(())
+            }
+
+            Command::UnpinTag(tag) => {
+                unpin_tag(&self.store, &tag).await?;
+                self.list_store().await?;
+                Ok(())
+            }
+
+            // Serve a tag from now on , also after a restart
+            Command::SeedTag((tag, hash)) => {
+                anyhow::ensure!(self.collector.is_some(), "the store is in memory");
+                seed_tag(&self.store, &tag, hash).await?;
+                self.update_seeds().await?;
+                self.mess
+                    .correct(format!("Seeding {}", tag).as_str())
+                    .await?;
+                self.list_store().await?;
+                Ok(())
+            }
+
+            Command::UnseedTag(tag) => {
+                unseed_tag(&self.store, &tag).await?;
+                self.update_seeds().await?;
+                self.mess
+                    .info(format!("Stopped seeding {}", tag).as_str())
+                    .await?;
+                self.list_store().await?;
+                Ok(())
+            }
+
+            Command::ListSeeds => {
+                self.send_seeds().await?;
+                Ok(())
+            }
+
+            // Peers from the config
+            Command::ReplicaSettings((peers, replicate_shares)) => {
+                *self.peers.lock().expect("peer lock") = peers.into_iter().collect();
+                self.replicate_shares = replicate_shares;
+                self.update_seeds().await?;
+                Ok(())
+            }
+
+            Command::Replicate((tag, hash)) => {
+                self.replicate(tag, hash).await?;
+                Ok(())
+            }
+
+            // Take offers from contacts
+            Command::InboxSettings(listen) => {
+                self.inbox.set_listen(listen);
+                self.update_seeds().await?;
+                Ok(())
+            }
+
+            Command::Push((to, offer)) => {
+                self.push(to, offer).await?;
+                Ok(())
+            }
+
+            Command::MoveStore((target, remove_old)) => {
+                self.move_store(target, remove_old).await?;
+                Ok(())
+            }
+
+            // Store limits from the config
+            Command::GcSettings(options) => {
+                self.gc_options = options;
+                self.schedule_gc();
+                Ok(())
+            }
+
+            // Relays from the config , new endpoints pick them up.
+            // Sent at start , the seeds from the last run start here.
+            Command::NetSettings(net) => {
+                if net != self.net {
+                    info!("network settings {net:?}");
+                    self.net = net;
+                    // The seeding node binds again with the new settings
+                    self.stop_seeding().await;
+                    self.mess.nearby(Vec::new()).await?;
+                }
+                self.update_seeds().await?;
+                Ok(())
+            }
+
+            Command::CollectGarbage => {
+                self.collect_garbage().await?;
+                Ok(())
+            }
+
+            // Read the whole store back
+            Command::VerifyStore => {
+                self.mess.info("Verifying the store...").await?;
+                self.start_timer().await?;
+                let res = verify_store(&self.store, &self.mess).await;
+                self.reset_timer().await?;
+                let report = res?;
+                let text = format!(
+                    "{} blobs , {} checked , {} damaged",
+                    report.checked,
+                    format_size(report.bytes, DECIMAL),
+                    report.damaged.len()
+                );
+                if report.damaged.is_empty() {
+                    self.mess.correct(text.as_str()).await?;
+                } else {
+                    self.mess.error(text.as_str()).await?;
+                }
+                self.mess.verify(report).await?;
+                Ok(())
+            }
+
+            Command::RepairBlob(hash) => {
+                repair_blob(&self.store, hash, &self.mess).await?;
+                self.mess
+                    .correct(format!("Repaired {}", hash.fmt_short()).as_str())
+                    .await?;
+                self.mess.blob_fixed(hash).await?;
+                Ok(())
+            }
+
+            // Give up on a damaged blob and what holds it
+            Command::DropBlob(hash) => {
+                let tags = drop_blob(&self.store, hash).await?;
+                self.mess
+                    .info(
+                        format!("Dropped {} , removed {} tags", hash.fmt_short(), tags.len())
+                            .as_str(),
+                    )
+                    .await?;
+                self.mess.blob_fixed(hash).await?;
+                self.update_seeds().await?;
+                self.collect_garbage().await?;
+                Ok(())
+            }
+
+            // Look at what is behind a ticket before fetching
+            Command::Preview(ticket) => {
+                let preview =
+                    preview(ticket, &self.net, self.mess.clone(), self.store.clone()).await?;
+                self.mess.preview(preview).await?;
+                Ok(())
             }
 
             // This is working.end with a UI reset.
-            Command::Fetch((ticket, target)) => {
-                let target_path = PathBuf::from(target);
+            Command::Fetch((ticket, target, options)) => {
                 self.start_timer().await?;
-                match receive(ticket, target_path, self.mess.clone(), self.store.clone()).await {
+                self.limits.fetch.set(options.download_limit);
+                let res = receive(
+                    ticket,
+                    target,
+                    options,
+                    &self.net,
+                    &self.limits,
+                    self.mess.clone(),
+                    self.store.clone(),
+                )
+                .await;
+                match res {
                     Ok(_) => {
                         self.reset_timer().await?;
+                        // Sweep out what was not kept
+                        if !options.keep_in_store {
+                            self.collect_garbage().await?;
+                        }
                         self.mess.finished().await?;
                     }
                     Err(err) => {
@@ -146,17 +541,370 @@ impl Worker {
                         return Err(err);
                     }
                 };
-                return Ok(());
+                Ok(())
+            }
+
+            // Same as a fetch , from a file
+            Command::ImportBundle((path, target, options)) => {
+                self.start_timer().await?;
+                let res =
+                    import_bundle(path, target, options, self.mess.clone(), self.store.clone())
+                        .await;
+                self.reset_timer().await?;
+                res?;
+                if !options.keep_in_store {
+                    self.collect_garbage().await?;
+                }
+                self.mess.finished().await?;
+                Ok(())
             }
 
             // Cancel testing
-            Command::CancelTest =>  { 
+            Command::CancelTest => {
                 info!("Cancel!!");
-                return Ok(());
+                Ok(())
             }
         }
     }
 
+    // -----
+    // Share functions
+    //------
+
+    fn start_share(&mut self, share: Share) {
+        let stop = self.share_stop();
+        self.spawn_share(serve(
+            share,
+            self.share_net(),
+            self.limits.clone(),
+            self.mess.clone(),
+            self.store.clone(),
+            stop,
+        ));
+    }
+
+    // The seeding node keeps the fixed ports while it runs , a share then
+    // goes on a random one
+    fn share_net(&self) -> NetOptions {
+        if self.seeder.is_some() {
+            self.net.random_ports()
+        } else {
+            self.net.clone()
+        }
+    }
+
+    fn sharing(&self) -> bool {
+        self.share_task
+            .as_ref()
+            .is_some_and(|task| !task.is_finished())
+    }
+
+    // Serve as a separate task so the worker keeps taking commands
+    fn spawn_share(&mut self, task: impl Future<Output = Result<()>> + Send + 'static) {
+        let mess = self.mess.clone();
+        self.share_task = Some(tokio::spawn(async move {
+            if let Err(err) = task.await {
+                let _ = mess.error(format!("{}", err).as_str()).await;
+                warn!("share failed {err}");
+            }
+        }));
+    }
+
+    // New stop signal for the next share
+    fn share_stop(&mut self) -> oneshot::Receiver<()> {
+        let (stop_tx, stop_rx) = oneshot::channel();
+        self.share = Some(stop_tx);
+        stop_rx
+    }
+
+    fn stop_share(&mut self) {
+        if let Some(stop) = self.share.take() {
+            let _ = stop.send(());
+        }
+    }
+
+    // Stop and wait until the share has let go of the store
+    async fn finish_share(&mut self) {
+        self.stop_share();
+        if let Some(task) = self.share_task.take() {
+            let _ = task.await;
+        }
+    }
+
+    // -----
+    // Store location
+    //------
+
+    // Copy the store to a new folder and carry on from there.
+    // Any failure goes back to the old store.
+    async fn move_store(&mut self, target: PathBuf, remove_old: bool) -> Result<()> {
+        anyhow::ensure!(self.collector.is_some(), "the store is in memory");
+        self.finish_share().await;
+        self.stop_seeding().await;
+        self.mess
+            .info(format!("Moving store to {}", target.display()).as_str())
+            .await?;
+        let tags = count_tags(&self.store).await?;
+        self.store.sync_db().await?;
+        self.store.shutdown().await?;
+        match copy_store(&self.store_path, &target, tags, &self.mess).await {
+            Ok((store, collector)) => {
+                self.store = store;
+                self.collector = Some(collector);
+                let old = std::mem::replace(&mut self.store_path, target.clone());
+                if remove_old && let Err(err) = std::fs::remove_dir_all(&old) {
+                    warn!("could not remove old store {err}");
+                    self.mess
+                        .error(format!("Old store left at {}", old.display()).as_str())
+                        .await?;
+                }
+                self.mess.store_moved(target).await?;
+                self.mess.correct("Store moved").await?;
+            }
+            Err(err) => match open_store(&self.store_path).await {
+                Ok((store, collector)) => {
+                    self.store = store;
+                    self.collector = Some(collector);
+                    self.mess
+                        .error(
+                            format!("Store move failed , still using the old store : {err}")
+                                .as_str(),
+                        )
+                        .await?;
+                }
+                // The old store is shut down , there is nothing left to work with
+                Err(reopen) => {
+                    let text = format!(
+                        "Store move failed : {err} , and the old store did not open again : {reopen}"
+                    );
+                    self.mess.fatal(&text).await?;
+                    return Err(Fatal(text).into());
+                }
+            },
+        }
+        // The key moved with the store , the tickets stay the same
+        self.update_seeds().await?;
+        self.list_store().await?;
+        self.list_shares().await?;
+        Ok(())
+    }
+
+    // -----
+    // Seeding
+    //------
+
+    // Start , refresh or stop the seeding node to match the seed tags.
+    // It also runs while there are replication peers , the inbox is open
+    // or a push may still be fetched.
+    async fn update_seeds(&mut self) -> Result<()> {
+        if self.collector.is_none() {
+            return Ok(());
+        }
+        // Peers , nearby devices and pushes need the node up even with
+        // nothing seeded
+        let idle = self.peers.lock().expect("peer lock").is_empty()
+            && !self.net.local_discovery
+            && !self.inbox.listening()
+            && self.pushes == 0;
+        match &self.seeder {
+            Some(seeder) => {
+                seeder.update(&self.store).await?;
+                if seeder.is_empty() && idle {
+                    self.stop_seeding().await;
+                }
+            }
+            None => {
+                if seeded(&self.store).await?.is_empty() && idle {
+                    return Ok(());
+                }
+                // A running share already holds the fixed ports
+                let net = if self.sharing() {
+                    self.net.random_ports()
+                } else {
+                    self.net.clone()
+                };
+                let seeder = Seeder::start(
+                    &self.store,
+                    &self.store_path,
+                    self.peers.clone(),
+                    self.inbox.clone(),
+                    &net,
+                    &self.limits,
+                    self.mess.clone(),
+                )
+                .await?;
+                if !seeder.is_empty() {
+                    self.mess
+                        .correct(format!("Seeding {} tags", seeder.stats().len()).as_str())
+                        .await?;
+                }
+                self.mess.node_id(seeder.node_id()).await?;
+                self.seeder = Some(seeder);
+            }
+        }
+        self.send_seeds().await
+    }
+
+    // New shares go to the peers when the config asks for it
+    async fn replicate_share(&mut self, hash: Hash) -> Result<()> {
+        if self.peers.lock().expect("peer lock").is_empty() {
+            return Ok(());
+        }
+        let shares = list_tags(&self.store, OUTGOING).await?;
+        if let Some(entry) = shares.into_iter().find(|entry| entry.hash == hash) {
+            self.replicate(entry.tag, hash).await?;
+        }
+        Ok(())
+    }
+
+    // Counters and tickets for the dashboard
+    async fn send_seeds(&self) -> Result<()> {
+        let stats = match &self.seeder {
+            Some(seeder) => seeder.stats(),
+            None => Vec::new(),
+        };
+        self.mess.seeding(stats).await?;
+        Ok(())
+    }
+
+    // Push a tag to every peer , each push reports on its own
+    async fn replicate(&mut self, tag: String, hash: Hash) -> Result<()> {
+        anyhow::ensure!(self.collector.is_some(), "the store is in memory");
+        let peers = self.peers.lock().expect("peer lock").clone();
+        anyhow::ensure!(!peers.is_empty(), "no replication peers configured");
+        self.update_seeds().await?;
+        let Some(seeder) = &self.seeder else {
+            anyhow::bail!("the seeding node is not running");
+        };
+        // The peers fetch it from the seeding node
+        seeder.offer(hash);
+        for peer in peers {
+            let endpoint = seeder.endpoint().clone();
+            let mess = self.mess.clone();
+            let tag = tag.clone();
+            tokio::spawn(async move {
+                let status = |state| ReplicaStatus {
+                    tag: tag.clone(),
+                    peer,
+                    state,
+                };
+                let _ = mess.replica(status(ReplicaState::Running)).await;
+                let state = match push_replica(&endpoint, peer, hash).await {
+                    Ok(bytes) => ReplicaState::Done(bytes),
+                    Err(err) => {
+                        warn!("replica of {tag} to {peer} failed {err}");
+                        ReplicaState::Failed(err.to_string())
+                    }
+                };
+                let _ = mess.replica(status(state)).await;
+            });
+        }
+        Ok(())
+    }
+
+    // Offer a collection to a contact , the answer comes back as a message
+    async fn push(&mut self, to: NodeAddr, offer: Offer) -> Result<()> {
+        anyhow::ensure!(self.collector.is_some(), "the store is in memory");
+        self.pushes += 1;
+        if let Err(err) = self.update_seeds().await {
+            self.pushes -= 1;
+            return Err(err);
+        }
+        let Some(seeder) = &self.seeder else {
+            self.pushes -= 1;
+            anyhow::bail!("the seeding node is not running");
+        };
+        seeder.offer(offer.hash);
+        let endpoint = seeder.endpoint().clone();
+        let fetched = seeder.fetched_by(to.node_id, FETCH_WAIT);
+        let done = self.push_done_tx.clone();
+        let mess = self.mess.clone();
+        self.mess
+            .info(format!("Offering {} to {}", offer.name, to.node_id.fmt_short()).as_str())
+            .await?;
+        tokio::spawn(async move {
+            let name = offer.name.clone();
+            let peer = to.node_id.fmt_short();
+            match push_offer(&endpoint, to, offer).await {
+                Ok(true) => {
+                    let _ = mess
+                        .correct(format!("{} accepted {}", peer, name).as_str())
+                        .await;
+                    fetched.await;
+                }
+                Ok(false) => {
+                    let _ = mess
+                        .info(format!("{} declined {}", peer, name).as_str())
+                        .await;
+                }
+                Err(err) => {
+                    warn!("offer of {name} to {peer} failed {err}");
+                    let _ = mess
+                        .error(format!("Offer of {} failed , {}", name, err).as_str())
+                        .await;
+                }
+            }
+            let _ = done.send(()).await;
+        });
+        Ok(())
+    }
+
+    async fn stop_seeding(&mut self) {
+        if let Some(seeder) = self.seeder.take()
+            && let Err(err) = seeder.shutdown().await
+        {
+            warn!("seeding node did not stop cleanly {err}");
+        }
+    }
+
+    // -----
+    // Garbage collection
+    //------
+
+    fn schedule_gc(&mut self) {
+        self.gc_due = match self.gc_options.interval_hours {
+            _ if self.collector.is_none() => None,
+            0 => None,
+            hours => Some(Instant::now() + Duration::from_secs(hours * 60 * 60)),
+        };
+    }
+
+    async fn collect_garbage(&mut self) -> Result<()> {
+        self.schedule_gc();
+        let Some(collector) = &self.collector else {
+            return Ok(());
+        };
+        self.mess.info("Cleaning up the store...").await?;
+        let report =
+            collect_garbage(&self.store, &self.store_path, collector, self.gc_options).await?;
+        self.mess
+            .correct(
+                format!(
+                    "Store cleaned , {} reclaimed , {} now",
+                    format_size(report.reclaimed(), DECIMAL),
+                    format_size(report.after, DECIMAL)
+                )
+                .as_str(),
+            )
+            .await?;
+        self.mess.gc_report(report).await?;
+        self.list_store().await?;
+        self.list_shares().await?;
+        Ok(())
+    }
+
+    async fn list_store(&self) -> Result<()> {
+        let tags = list_tags(&self.store, "").await?;
+        self.mess.store_tags(tags).await?;
+        Ok(())
+    }
+
+    async fn list_shares(&self) -> Result<()> {
+        let shares = list_tags(&self.store, OUTGOING).await?;
+        self.mess.shares(shares).await?;
+        Ok(())
+    }
+
     // -----
     // Timer functions
     //------
@@ -195,7 +943,7 @@ impl TimerTask {
     }
 
     pub fn run(self, incoming: Receiver<TimerCommands>) {
-        let _ = tokio::spawn(async move {
+        tokio::spawn(async move {
             // every second , variables are local to the thread.
             let mut interval = interval(Duration::from_millis(1000));
             let mut running = true;